@@ -1,8 +1,14 @@
 use anyhow::{anyhow, Error, Result};
+use brotli::enc::BrotliEncoderParams;
 use css_minify::optimizations::{Level, Minifier};
+use data_encoding::BASE64;
+use flate2::{write::GzEncoder, Compression};
+use sha2::{Digest, Sha256, Sha384};
 use std::{
     borrow::Cow,
-    env, fs, iter,
+    env, fs,
+    io::Write,
+    iter,
     path::{Path, PathBuf},
 };
 
@@ -45,6 +51,7 @@ fn main() -> Result<()> {
         minify_css(debug, &config)?;
         minify_templates(debug, &config)?;
     }
+    write_assets(debug, &config)?;
     Ok(())
 }
 /// Indicate for cargo to rerun if any file in this project that are embedded in the binary are
@@ -57,6 +64,12 @@ fn rerun_if_change(debug: bool, paths: &BuildConfig) -> Result<()> {
             format!("cargo:rerun-if-changed={}", tmpl.to_string_lossy()),
         );
     }
+    for asset in paths.src_assets() {
+        println_with_debug(
+            debug,
+            format!("cargo:rerun-if-changed={}", asset.to_string_lossy()),
+        );
+    }
     Ok(())
 }
 #[derive(Debug)]
@@ -166,6 +179,47 @@ impl BuildConfig {
                 .map_or_else(Err, |res| res)
         })
     }
+    /// The source files of every embedded static asset, unminified.
+    pub fn src_assets(&self) -> Vec<PathBuf> {
+        self.assets()
+            .into_iter()
+            .map(|asset| match asset.dir {
+                AssetDir::Css | AssetDir::Static => self.src_static_dir.join(asset.path),
+                AssetDir::Vendor => self.vendor_dir.join(asset.path),
+            })
+            .collect()
+    }
+    /// Every static asset embedded in the binary, served under `/static/`.
+    pub fn assets(&self) -> Vec<Asset> {
+        let mut assets = vec![
+            Asset {
+                path: "style.css",
+                content_type: "text/css",
+                dir: AssetDir::Css,
+            },
+            Asset {
+                path: "htmx-1.9.2/htmx.min.js",
+                content_type: "text/javascript",
+                dir: AssetDir::Vendor,
+            },
+        ];
+        if env::var_os("CARGO_FEATURE_LOCAL_DEV").is_some() {
+            assets.push(Asset {
+                path: "dev_restart.js",
+                content_type: "text/javascript",
+                dir: AssetDir::Static,
+            });
+        }
+        assets
+    }
+    /// The active file of the given asset, minified if applicable.
+    pub fn asset_file(&self, asset: &Asset) -> PathBuf {
+        match asset.dir {
+            AssetDir::Css => self.css_dir().join(asset.path),
+            AssetDir::Static => self.src_static_dir.join(asset.path),
+            AssetDir::Vendor => self.vendor_dir.join(asset.path),
+        }
+    }
     /// List both src and out css.
     pub fn list_css(&self) -> impl Iterator<Item = Result<SrcOutPaths>> + '_ {
         self.list_files_recur(self.src_static_dir.as_path())
@@ -192,6 +246,20 @@ impl BuildConfig {
     }
 }
 #[derive(Debug)]
+struct Asset {
+    /// The path served under `/static/`, relative to its dir.
+    pub path: &'static str,
+    pub content_type: &'static str,
+    pub dir: AssetDir,
+}
+#[derive(Debug, Clone, Copy)]
+enum AssetDir {
+    /// The active css dir, see [`BuildConfig::css_dir`].
+    Css,
+    Static,
+    Vendor,
+}
+#[derive(Debug)]
 struct SrcOutPaths {
     pub src: PathBuf,
     pub out: PathBuf,
@@ -209,7 +277,7 @@ fn minify_css(debug: bool, config: &BuildConfig) -> Result<()> {
         fs::create_dir_all(out_parent).expect("failed to create parent dir to template dst");
         let minified_buf = {
             let src = fs::read_to_string(&src_path)?;
-            let buf = Minifier::default()
+            Minifier::default()
                 .minify(&src, Level::One)
                 // minify error has a lifetime, so it won't work with ? and anyhow.
                 .map_err(|err| {
@@ -218,8 +286,7 @@ fn minify_css(debug: bool, config: &BuildConfig) -> Result<()> {
                         src_path.to_string_lossy(),
                         err
                     )
-                })?;
-            buf
+                })?
         };
         fs::write(out_path, minified_buf)?;
     }
@@ -247,6 +314,63 @@ fn minify_templates(debug: bool, config: &BuildConfig) -> Result<()> {
     }
     Ok(())
 }
+/// Write `assets.rs` to the out dir, listing every embedded asset along with a hash of its
+/// contents and its gzip and brotli variants, for `static_assets` to include.
+fn write_assets(debug: bool, config: &BuildConfig) -> Result<()> {
+    let out_dir =
+        PathBuf::from(env::var_os("OUT_DIR").ok_or_else(|| anyhow!("OUT_DIR not specified"))?);
+    let compressed_dir = out_dir.join("compressed");
+    let mut src = String::from("// Generated by `build.rs`, see `BuildConfig::assets`.\n[\n");
+    for asset in config.assets() {
+        let file = config.asset_file(&asset);
+        log_with_debug(debug, format!("hashing: {}", file.to_string_lossy()));
+        let bytes = fs::read(&file)?;
+        let hash = Sha256::digest(&bytes)
+            .iter()
+            .take(8)
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        // Subresource integrity of the content, see `static_assets::asset_integrity`.
+        let integrity = format!("sha384-{}", BASE64.encode(&Sha384::digest(&bytes)));
+        log_with_debug(debug, format!("compressing: {}", file.to_string_lossy()));
+        let gzip_file = compressed_dir.join(format!("{}.gz", asset.path));
+        let brotli_file = compressed_dir.join(format!("{}.br", asset.path));
+        let parent = gzip_file
+            .parent()
+            .ok_or_else(|| anyhow!("parent did not exist for: {}", gzip_file.to_string_lossy()))?;
+        fs::create_dir_all(parent)?;
+        fs::write(&gzip_file, gzip(&bytes)?)?;
+        fs::write(&brotli_file, brotli(&bytes)?)?;
+        src.push_str(&format!(
+            "    GeneratedAsset {{ path: {:?}, content_type: {:?}, hash: {:?}, integrity: {:?}, \
+             bytes: include_bytes!({:?}), gzip: include_bytes!({:?}), brotli: include_bytes!({:?}) }},\n",
+            asset.path,
+            asset.content_type,
+            hash,
+            integrity,
+            file.to_string_lossy(),
+            gzip_file.to_string_lossy(),
+            brotli_file.to_string_lossy(),
+        ));
+    }
+    src.push_str("]\n");
+    fs::write(out_dir.join("assets.rs"), src)?;
+    Ok(())
+}
+fn gzip(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?)
+}
+fn brotli(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    let params = BrotliEncoderParams {
+        quality: 11,
+        ..Default::default()
+    };
+    brotli::BrotliCompress(&mut &bytes[..], &mut compressed, &params)?;
+    Ok(compressed)
+}
 fn log_with_debug(debug: bool, s: impl AsRef<str>) {
     if debug {
         let s = s.as_ref();
@@ -1,12 +1,20 @@
 use clap::Parser;
-use std::sync::Arc;
-use summit::{db::DbConfig, web::ServeConfig, Summit};
-use tracing::{metadata::LevelFilter, subscriber};
+use std::{sync::Arc, time::Duration};
+use summit::{
+    db::DbConfig,
+    fedi::delivery::DeliveryQueue,
+    web::{shutdown::ShutdownSignal, ServeConfig},
+    Summit, SummitConfig,
+};
+use tokio::task::JoinHandle;
+use tracing::{error, info, metadata::LevelFilter, subscriber, warn};
 use tracing_subscriber::EnvFilter;
 
 #[derive(Parser, Debug, Default)]
 #[command(author, version, about, long_about = None)]
 pub struct CliConfig {
+    #[command(flatten)]
+    pub summit: SummitConfig,
     #[command(flatten)]
     pub db: DbConfig,
     #[command(flatten)]
@@ -32,13 +40,83 @@ async fn main() -> Result<(), hyper::Error> {
     .unwrap();
 
     let config = CliConfig::parse();
-    let summit = Arc::new(Summit::new(config.db.init()));
+    let shutdown_signal = ShutdownSignal::new(Duration::from_millis(config.serve.drain_ms)).await;
+    let shutdown_timeout = Duration::from_millis(config.serve.shutdown_timeout_ms);
+    let db = config.db.init(config.summit.fedi.host());
+    let summit = Arc::new(Summit::new(config.summit, db));
+    let deliveries = tokio::spawn(DeliveryQueue::run(
+        Arc::clone(&summit),
+        shutdown_signal.clone(),
+        shutdown_timeout,
+    ));
+    let content_processing = tokio::spawn({
+        let summit = Arc::clone(&summit);
+        let shutdown_signal = shutdown_signal.clone();
+        async move { summit.process_content(shutdown_signal).await }
+    });
     #[cfg(any(test, feature = "dev"))]
-    {
-        tracing::info!("running with dev");
-        let fake = config.fake.init(Arc::clone(&summit)).await;
-        summit::web::serve(config.serve, summit, fake).await
-    }
+    let res = {
+        info!("running with dev");
+        let (fake, fake_runtime) = config
+            .fake
+            .init(Arc::clone(&summit), shutdown_signal.clone())
+            .await;
+        let res = summit::web::serve(
+            config.serve,
+            Arc::clone(&summit),
+            shutdown_signal.clone(),
+            fake,
+        )
+        .await;
+        // Serving may have failed before any signal, so stop the background tasks either way.
+        shutdown_signal.shutdown();
+        if let Some(fake_runtime) = fake_runtime {
+            finish("fake user runtime", fake_runtime, shutdown_timeout).await;
+        }
+        res
+    };
     #[cfg(not(any(test, feature = "dev")))]
-    summit::web::serve(config.serve, summit).await
+    let res = {
+        let res =
+            summit::web::serve(config.serve, Arc::clone(&summit), shutdown_signal.clone()).await;
+        // Serving may have failed before any signal, so stop the background tasks either way.
+        shutdown_signal.shutdown();
+        res
+    };
+
+    // No longer accepting connections, live streams are closed, and nothing queues more posts.
+    // Drain the content process queue and finish in flight deliveries, then release whatever
+    // deliveries are left and close the Db.
+    info!("server stopped, finishing in flight work..");
+    finish("content processing", content_processing, shutdown_timeout).await;
+    match tokio::time::timeout(shutdown_timeout, summit.process_queued_content()).await {
+        Ok(0) => {},
+        Ok(drained) => info!(drained, "drained content process queue"),
+        // Posts are saved before they're queued, so only their processing is lost.
+        Err(_) => warn!(
+            ?shutdown_timeout,
+            "content process queue didn't drain before shutdown timeout"
+        ),
+    }
+    // Attempts are aborted on the timeout, so none finishes after its delivery is released.
+    if let Err(err) = deliveries.await {
+        error!(?err, "delivery queue failed");
+    }
+    if let Err(err) = summit.close().await {
+        error!(?err, "failed to close summit");
+    }
+    info!("shutdown complete");
+    res
+}
+
+/// Wait on a task stopping for shutdown, aborting it once it takes longer than `timeout`.
+async fn finish(name: &str, mut task: JoinHandle<()>, timeout: Duration) {
+    match tokio::time::timeout(timeout, &mut task).await {
+        Ok(Ok(())) => {},
+        Ok(Err(err)) => error!(?err, name, "task failed"),
+        Err(_) => {
+            warn!(?timeout, name, "aborting task after shutdown timeout");
+            task.abort();
+        },
+    }
 }
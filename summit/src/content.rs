@@ -0,0 +1,268 @@
+//! Post content processing, such as extracting `@user@host` mentions and `#tags` from Markdown and
+//! linking them when rendered.
+use crate::fedi::addr::FediAddr;
+use compact_str::CompactString;
+use pulldown_cmark::{CowStr, Event, Parser, Tag};
+use serde::{Deserialize, Serialize};
+
+/// The mentions and tags referenced by some content.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentRefs {
+    pub mentions: Vec<FediAddr>,
+    /// Normalized, lowercase tags without the leading `#`.
+    pub tags: Vec<CompactString>,
+}
+impl ContentRefs {
+    /// Scan the given Markdown sources for references, ignoring any within code or links.
+    pub fn scan<'a>(markdown: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut refs = Self::default();
+        for src in markdown {
+            for text in text_events(Parser::new(src)) {
+                for token in tokenize(&text) {
+                    match token {
+                        Token::Mention(_, addr) if !refs.mentions.contains(&addr) => {
+                            refs.mentions.push(addr)
+                        },
+                        Token::Tag(_, tag) if !refs.tags.contains(&tag) => refs.tags.push(tag),
+                        _ => {},
+                    }
+                }
+            }
+        }
+        refs
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token<'a> {
+    Text(&'a str),
+    /// A mention, with the source text it was parsed from.
+    Mention(&'a str, FediAddr),
+    /// A tag, with the source text it was parsed from.
+    Tag(&'a str, CompactString),
+}
+
+/// Split plain text into text, mention and tag tokens.
+pub fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut text_start = 0;
+    let mut prev = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_boundary = prev.map_or(true, |prev: char| !is_word_char(prev));
+        prev = Some(c);
+        if !at_boundary || !(c == '@' || c == '#') {
+            continue;
+        }
+        let matched = if c == '@' {
+            match_mention(&text[i..])
+                .map(|(len, addr)| (len, Token::Mention(&text[i..i + len], addr)))
+        } else {
+            match_tag(&text[i..]).map(|(len, tag)| (len, Token::Tag(&text[i..i + len], tag)))
+        };
+        let Some((len, token)) = matched else {
+            continue;
+        };
+        if text_start < i {
+            tokens.push(Token::Text(&text[text_start..i]));
+        }
+        tokens.push(token);
+        text_start = i + len;
+        while chars.peek().map_or(false, |&(j, _)| j < text_start) {
+            prev = chars.next().map(|(_, c)| c);
+        }
+    }
+    if text_start < text.len() {
+        tokens.push(Token::Text(&text[text_start..]));
+    }
+    tokens
+}
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '@' | '#' | '/' | '.' | '-')
+}
+/// Match a `@user@host` mention at the start of `s`, returning the matched length.
+fn match_mention(s: &str) -> Option<(usize, FediAddr)> {
+    let rest = s.strip_prefix('@')?;
+    let user_len = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-')))
+        .unwrap_or(rest.len());
+    let host = rest[user_len..].strip_prefix('@')?;
+    let host_len = host
+        .find(|c: char| !(c.is_alphanumeric() || matches!(c, '.' | '-' | ':')))
+        .unwrap_or(host.len());
+    // Trailing punctuation most likely ends the sentence rather than the host.
+    let host = host[..host_len].trim_end_matches(['.', '-', ':']);
+    let len = 1 + user_len + 1 + host.len();
+    let addr = FediAddr::parse(&s[..len]).ok()?;
+    Some((len, addr))
+}
+/// Match a `#tag` at the start of `s`, returning the matched length and normalized tag.
+fn match_tag(s: &str) -> Option<(usize, CompactString)> {
+    let rest = s.strip_prefix('#')?;
+    let len = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    let tag = &rest[..len];
+    // Purely numeric tags are far more often issue numbers and the like.
+    if tag.is_empty() || tag.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((1 + len, tag.to_lowercase().into()))
+}
+
+/// Merge adjacent text events. The parser splits text on characters that may be syntax, such as
+/// the `_` often found in usernames, which would otherwise break up mentions.
+fn merge_text<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let mut merged: Vec<Event<'a>> = Vec::new();
+    for event in events {
+        match (merged.last_mut(), event) {
+            (Some(Event::Text(prev)), Event::Text(text)) => {
+                *prev = CowStr::from(format!("{prev}{text}"));
+            },
+            (_, event) => merged.push(event),
+        }
+    }
+    merged
+}
+/// Text events outside of code and links, where references are meaningful.
+fn text_events<'a>(events: impl Iterator<Item = Event<'a>>) -> impl Iterator<Item = CowStr<'a>> {
+    let mut depth = 0usize;
+    merge_text(events)
+        .into_iter()
+        .filter_map(move |event| match event {
+            Event::Start(tag) if is_opaque(&tag) => {
+                depth += 1;
+                None
+            },
+            Event::End(tag) if is_opaque(&tag) => {
+                depth = depth.saturating_sub(1);
+                None
+            },
+            Event::Text(text) if depth == 0 => Some(text),
+            _ => None,
+        })
+}
+fn is_opaque(tag: &Tag<'_>) -> bool {
+    matches!(tag, Tag::CodeBlock(_) | Tag::Link(..) | Tag::Image(..))
+}
+
+/// Replace mentions and tags within the given Markdown events with links to their profile and tag
+/// pages.
+pub fn linkify<'a>(events: impl Iterator<Item = Event<'a>>) -> impl Iterator<Item = Event<'a>> {
+    let mut depth = 0usize;
+    merge_text(events).into_iter().flat_map(move |event| {
+        let events = match event {
+            Event::Start(ref tag) if is_opaque(tag) => {
+                depth += 1;
+                vec![event]
+            },
+            Event::End(ref tag) if is_opaque(tag) => {
+                depth = depth.saturating_sub(1);
+                vec![event]
+            },
+            Event::Text(ref text) if depth == 0 => {
+                let tokens = tokenize(text);
+                if tokens.iter().all(|token| matches!(token, Token::Text(_))) {
+                    vec![event]
+                } else {
+                    tokens.into_iter().map(token_event).collect()
+                }
+            },
+            event => vec![event],
+        };
+        events.into_iter()
+    })
+}
+fn token_event<'a>(token: Token<'_>) -> Event<'a> {
+    match token {
+        Token::Text(text) => Event::Text(CowStr::from(text.to_owned())),
+        // Both are restricted to characters safe to use in html, by parsing.
+        Token::Mention(_, addr) => Event::Html(CowStr::from(format!(
+            r#"<a class="mention" href="/u/{user}@{host}">@{user}@{host}</a>"#,
+            user = addr.user,
+            host = addr.host,
+        ))),
+        Token::Tag(src, tag) => Event::Html(CowStr::from(format!(
+            r#"<a class="hashtag" href="/t/{tag}">{src}</a>"#
+        ))),
+    }
+}
+
+/// The plain text of the given Markdown, with whitespace collapsed, and cut at a word boundary to
+/// at most `max_chars` characters, ending with `…` if cut. Such as for link preview descriptions.
+pub fn snippet(markdown: &str, max_chars: usize) -> String {
+    let mut text = String::new();
+    let mut len = 0;
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Text(s) | Event::Code(s) => {
+                text.push_str(&s);
+                len += s.chars().filter(|c| !c.is_whitespace()).count();
+            },
+            Event::SoftBreak
+            | Event::HardBreak
+            | Event::End(
+                Tag::Paragraph | Tag::Heading(..) | Tag::Item | Tag::BlockQuote | Tag::CodeBlock(_),
+            ) => text.push(' '),
+            _ => {},
+        }
+        // Enough to fill the snippet, even once whitespace is collapsed.
+        if len > max_chars {
+            break;
+        }
+    }
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= max_chars {
+        return text;
+    }
+    let cut = text
+        .char_indices()
+        .nth(max_chars.saturating_sub(1))
+        .map_or(text.len(), |(i, _)| i);
+    let cut = text[..cut].rfind(' ').unwrap_or(cut);
+    format!("{}…", text[..cut].trim_end())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scan_refs() {
+        let refs = ContentRefs::scan([
+            "Hey @Alice@summit.example, see #Rust and #rust.",
+            "`@code@host.example #code` and [#link](/x) but not email@host.example or #123",
+        ]);
+        assert_eq!(
+            refs.mentions,
+            [FediAddr {
+                user: "alice".into(),
+                host: "summit.example".into(),
+            }]
+        );
+        assert_eq!(refs.tags, ["rust"]);
+    }
+    #[test]
+    fn linkify_html() {
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(
+            &mut html,
+            linkify(Parser::new("hi @bob_b@host.example! #Tag `#not`")),
+        );
+        assert_eq!(
+            html,
+            "<p>hi <a class=\"mention\" href=\"/u/bob_b@host.example\">@bob_b@host.example</a>! \
+             <a class=\"hashtag\" href=\"/t/tag\">#Tag</a> <code>#not</code></p>\n"
+        );
+    }
+    #[test]
+    fn snippets() {
+        assert_eq!(
+            snippet("# Hi\n\nSome *emphasis*, `code`\nand [a link](/x).", 100),
+            "Hi Some emphasis, code and a link."
+        );
+        assert_eq!(snippet("one two three four", 12), "one two…");
+        assert_eq!(snippet("onetwothree", 5), "onet…");
+        assert_eq!(snippet("", 10), "");
+    }
+}
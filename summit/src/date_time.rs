@@ -1,46 +1,326 @@
-use chrono::{FixedOffset, Utc};
+use chrono::{FixedOffset, Offset, TimeZone as _, Utc};
+use chrono_tz::Tz;
 use sailfish::{
     runtime::{Buffer, Render},
     RenderError,
 };
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    fmt::{self, Write},
+    ops::Add,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+mod format;
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+use self::format::Clock;
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
 pub struct DateTime(chrono::DateTime<Utc>);
 impl DateTime {
     pub fn now() -> Self {
         Self(Utc::now())
     }
-    pub fn to_local(self, tz: TimeZone) -> LocalDateTime {
-        LocalDateTime(self, tz)
+    pub fn to_local(self, prefs: DateTimePrefs) -> LocalDateTime {
+        LocalDateTime(self, prefs)
+    }
+    /// Render as an RFC 3339 timestamp in UTC, such as for the `datetime` of `<time>` elements.
+    pub fn iso(self) -> IsoDateTime {
+        IsoDateTime(self)
+    }
+    /// Milliseconds since the unix epoch, negative before it.
+    pub fn timestamp_millis(self) -> i64 {
+        self.0.timestamp_millis()
+    }
+    /// The inverse of [`Self::timestamp_millis`], or `None` if out of range.
+    pub fn from_timestamp_millis(millis: i64) -> Option<Self> {
+        Utc.timestamp_millis_opt(millis).single().map(Self)
+    }
+}
+impl From<DateTime> for SystemTime {
+    /// Truncated to millisecond precision, such as for http date headers.
+    fn from(date_time: DateTime) -> Self {
+        let millis = date_time.timestamp_millis();
+        let since_epoch = Duration::from_millis(millis.unsigned_abs());
+        if millis < 0 {
+            UNIX_EPOCH - since_epoch
+        } else {
+            UNIX_EPOCH + since_epoch
+        }
+    }
+}
+impl Add<Duration> for DateTime {
+    type Output = Self;
+    fn add(self, dur: Duration) -> Self {
+        // Saturate rather than panic, durations this large are a bug but not worth crashing over.
+        chrono::Duration::from_std(dur)
+            .ok()
+            .and_then(|dur| self.0.checked_add_signed(dur))
+            .map_or(Self(chrono::DateTime::<Utc>::MAX_UTC), Self)
     }
 }
 
-// TODO: Support named zones and related features, for named selection. chrono_tz?
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct TimeZone(FixedOffset);
+pub enum TimeZone {
+    /// An IANA zone, such as `America/Los_Angeles`, observing daylight saving time where the zone
+    /// does.
+    Named(Tz),
+    /// A fixed offset from UTC, as zones were before named zones were supported.
+    Fixed(FixedOffset),
+}
 impl Default for TimeZone {
     fn default() -> Self {
-        // NOTE: Defaulting to west coast US for testing purposes, mostly.
-        // Long term this should be configurable for communities which have a natural time zone.
-        Self(FixedOffset::west_opt(7 * 3600).expect("7*3600 is within max seconds"))
+        // NOTE: Communities with a natural time zone can set their own default, see
+        // `db::Community`.
+        Self::Named(Tz::UTC)
+    }
+}
+impl TimeZone {
+    /// A fixed offset east of UTC, or `None` if out of bounds.
+    pub fn from_offset_secs(secs: i32) -> Option<Self> {
+        FixedOffset::east_opt(secs).map(Self::Fixed)
+    }
+    /// The offset from UTC at the given instant, which for named zones depends on daylight saving
+    /// time.
+    pub fn offset_secs_at(&self, date_time: DateTime) -> i32 {
+        match self {
+            Self::Named(tz) => date_time
+                .0
+                .with_timezone(tz)
+                .offset()
+                .fix()
+                .local_minus_utc(),
+            Self::Fixed(offset) => offset.local_minus_utc(),
+        }
+    }
+    /// Every named zone, for zone pickers.
+    pub fn named() -> impl Iterator<Item = Self> {
+        chrono_tz::TZ_VARIANTS.iter().copied().map(Self::Named)
+    }
+}
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("unknown time zone: {0}")]
+pub struct TimeZoneError(String);
+/// The furthest any zone is from UTC, being UTC+14:00.
+const MAX_OFFSET_HOURS: i32 = 14;
+impl FromStr for TimeZone {
+    type Err = TimeZoneError;
+    /// Parse either an IANA zone name, or a fixed offset such as `UTC+05:30` or `-07:00`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(tz) = s.parse::<Tz>() {
+            return Ok(Self::Named(tz));
+        }
+        let err = || TimeZoneError(s.to_owned());
+        let offset = s.strip_prefix("UTC").unwrap_or(s);
+        let (sign, offset) = match offset.split_at(offset.len().min(1)) {
+            ("+", offset) => (1, offset),
+            ("-", offset) => (-1, offset),
+            _ => return Err(err()),
+        };
+        let (hours, mins) = offset.split_once(':').unwrap_or((offset, "0"));
+        // Only digits, as the sign was already taken and `parse` would accept another.
+        let digits = |s: &str| {
+            (!s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()))
+                .then(|| s.parse::<i32>().ok())
+                .flatten()
+        };
+        let (hours, mins) = digits(hours).zip(digits(mins)).ok_or_else(err)?;
+        if hours > MAX_OFFSET_HOURS || mins >= 60 {
+            return Err(err());
+        }
+        let secs = hours
+            .checked_mul(3600)
+            .and_then(|secs| secs.checked_add(mins * 60))
+            .and_then(|secs| secs.checked_mul(sign))
+            .ok_or_else(err)?;
+        Self::from_offset_secs(secs).ok_or_else(err)
+    }
+}
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Named(tz) => f.write_str(tz.name()),
+            Self::Fixed(offset) => {
+                let secs = offset.local_minus_utc();
+                let sign = if secs < 0 { '-' } else { '+' };
+                let mins = secs.abs() / 60;
+                write!(f, "UTC{sign}{:02}:{:02}", mins / 60, mins % 60)
+            },
+        }
+    }
+}
+impl Serialize for TimeZone {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+impl Render for TimeZone {
+    #[inline]
+    fn render(&self, buf: &mut Buffer) -> Result<(), RenderError> {
+        match self {
+            Self::Named(tz) => buf.push_str(tz.name()),
+            Self::Fixed(_) => write!(buf, "{self}")?,
+        }
+        Ok(())
     }
 }
 
+/// How date times are displayed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateTimeFormat {
+    /// A date and 12 hour time, eg `Jun 19, 2023 4:05pm`.
+    #[default]
+    Absolute,
+    /// A date and 24 hour time, eg `Jun 19, 2023 16:05`.
+    #[serde(rename = "absolute_24h")]
+    Absolute24h,
+    /// Time elapsed, eg `3 minutes ago`.
+    Relative,
+    /// An RFC 3339 timestamp, eg `2023-06-19T16:05:00-07:00`.
+    Iso,
+}
+impl DateTimeFormat {
+    pub const ALL: [Self; 4] = [Self::Absolute, Self::Absolute24h, Self::Relative, Self::Iso];
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Absolute => "absolute",
+            Self::Absolute24h => "absolute_24h",
+            Self::Relative => "relative",
+            Self::Iso => "iso",
+        }
+    }
+}
+/// The date time display preferences of a user.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DateTimePrefs {
+    pub time_zone: TimeZone,
+    pub format: DateTimeFormat,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct LocalDateTime(DateTime, TimeZone);
+pub struct LocalDateTime(DateTime, DateTimePrefs);
 impl Render for LocalDateTime {
     #[inline]
     fn render(&self, buf: &mut Buffer) -> Result<(), RenderError> {
-        let &Self(DateTime(date_time_utc), TimeZone(offset)) = self;
-        let date_time = date_time_utc + offset;
-        // NIT: Is there a way we can render this without allocating? Seems every component of
-        // the formatted date time is a fixed set of characters, no need to alloc just to push a str
-        // right? :thinking:
-        //
-        // For now just using the native formatting for ease. No need to shave this yak.
-        //
-        // TODO: Time display preferences would be nice.
-        buf.push_str(&format!("{}", date_time.format("%b %m, %Y %l:%M%P")));
+        let &Self(date_time, DateTimePrefs { time_zone, format }) = self;
+        let date_time_utc = date_time.0;
+        // Converting through the zone, rather than adding a single offset, keeps named zones
+        // correct on either side of daylight saving time changes.
+        let date_time = date_time_utc.with_timezone(
+            &FixedOffset::east_opt(time_zone.offset_secs_at(date_time))
+                .expect("zone offsets to be within bounds"),
+        );
+        match format {
+            DateTimeFormat::Absolute => format::write_absolute(buf, &date_time, Clock::H12)?,
+            DateTimeFormat::Absolute24h => format::write_absolute(buf, &date_time, Clock::H24)?,
+            DateTimeFormat::Relative => {
+                format::write_relative(buf, Utc::now().signed_duration_since(date_time_utc))?
+            },
+            DateTimeFormat::Iso => format::write_iso(buf, &date_time)?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IsoDateTime(DateTime);
+impl Render for IsoDateTime {
+    #[inline]
+    fn render(&self, buf: &mut Buffer) -> Result<(), RenderError> {
+        format::write_iso(buf, &self.0 .0.fixed_offset())?;
         Ok(())
     }
 }
+impl fmt::Display for IsoDateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format::write_iso(f, &self.0 .0.fixed_offset())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn render(date_time: DateTime, time_zone: TimeZone) -> String {
+        render_as(date_time, time_zone, DateTimeFormat::Absolute24h)
+    }
+    fn render_as(date_time: DateTime, time_zone: TimeZone, format: DateTimeFormat) -> String {
+        let mut buf = Buffer::new();
+        let prefs = DateTimePrefs { time_zone, format };
+        date_time.to_local(prefs).render(&mut buf).unwrap();
+        buf.into_string()
+    }
+
+    #[test]
+    fn named_zones_observe_dst() {
+        let la = "America/Los_Angeles".parse::<TimeZone>().unwrap();
+        let winter = DateTime(Utc.with_ymd_and_hms(2023, 1, 15, 20, 0, 0).unwrap());
+        let summer = DateTime(Utc.with_ymd_and_hms(2023, 7, 15, 20, 0, 0).unwrap());
+        assert_eq!(render(winter, la), "Jan 15, 2023 12:00");
+        assert_eq!(render(summer, la), "Jul 15, 2023 13:00");
+        let fixed = "UTC-08:00".parse::<TimeZone>().unwrap();
+        assert_eq!(render(summer, fixed), "Jul 15, 2023 12:00");
+    }
+    #[test]
+    fn parse_time_zones() {
+        for s in ["Europe/Berlin", "UTC", "UTC+05:30", "UTC-07:00"] {
+            assert_eq!(s.parse::<TimeZone>().unwrap().to_string(), s);
+        }
+        assert_eq!(
+            "+9".parse::<TimeZone>(),
+            Ok(TimeZone::from_offset_secs(9 * 3600).unwrap())
+        );
+        assert!("Mars/Olympus_Mons".parse::<TimeZone>().is_err());
+        assert!("UTC+05:75".parse::<TimeZone>().is_err());
+        assert!("UTC+14:00".parse::<TimeZone>().is_ok());
+        // Out of range, rather than overflowing.
+        for s in ["+15", "+999999", "-99999999999", "UTC+14:99999999"] {
+            assert!(s.parse::<TimeZone>().is_err(), "{s}");
+        }
+        // Only a single sign.
+        for s in ["+-5", "-+5", "UTC++05:00", "+05:-30", "+05:+30"] {
+            assert!(s.parse::<TimeZone>().is_err(), "{s}");
+        }
+    }
+    #[test]
+    fn formats() {
+        let utc = TimeZone::default();
+        let date_time = DateTime(Utc.with_ymd_and_hms(2023, 7, 5, 16, 5, 9).unwrap());
+        assert_eq!(
+            render_as(date_time, utc, DateTimeFormat::Absolute),
+            "Jul 5, 2023 4:05pm"
+        );
+        assert_eq!(
+            render_as(date_time, utc, DateTimeFormat::Absolute24h),
+            "Jul 5, 2023 16:05"
+        );
+        let la = "America/Los_Angeles".parse::<TimeZone>().unwrap();
+        assert_eq!(
+            render_as(date_time, la, DateTimeFormat::Iso),
+            "2023-07-05T09:05:09-07:00"
+        );
+        let mut buf = Buffer::new();
+        date_time.iso().render(&mut buf).unwrap();
+        assert_eq!(buf.as_str(), "2023-07-05T16:05:09Z");
+    }
+    #[test]
+    fn relative() {
+        let relative = |secs| {
+            let mut s = String::new();
+            format::write_relative(&mut s, chrono::Duration::seconds(secs)).unwrap();
+            s
+        };
+        assert_eq!(relative(3), "just now");
+        assert_eq!(relative(59), "59 seconds ago");
+        assert_eq!(relative(3 * 60 + 5), "3 minutes ago");
+        assert_eq!(relative(3600), "1 hour ago");
+        assert_eq!(relative(-2 * 86_400), "in 2 days");
+        assert_eq!(relative(400 * 86_400), "1 year ago");
+    }
+}
@@ -0,0 +1,78 @@
+//! Date time formatting, writing directly into any [`fmt::Write`] such as a sailfish `Buffer`.
+//!
+//! Chrono's `format` collects into an intermediate `String` per call, and every component here is
+//! a number or a fixed string anyway, so they're written individually instead.
+use chrono::{Datelike, FixedOffset, Timelike};
+use std::fmt::{self, Write};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    H12,
+    H24,
+}
+
+/// Write a date and time, eg `Jul 5, 2023 4:05pm` or `Jul 5, 2023 16:05`.
+pub fn write_absolute(
+    w: &mut impl Write,
+    date_time: &chrono::DateTime<FixedOffset>,
+    clock: Clock,
+) -> fmt::Result {
+    write!(
+        w,
+        "{} {}, {} ",
+        MONTHS[date_time.month0() as usize],
+        date_time.day(),
+        date_time.year(),
+    )?;
+    match clock {
+        Clock::H12 => {
+            let (pm, hour) = date_time.hour12();
+            let meridiem = if pm { "pm" } else { "am" };
+            write!(w, "{hour}:{:02}{meridiem}", date_time.minute())
+        },
+        Clock::H24 => write!(w, "{:02}:{:02}", date_time.hour(), date_time.minute()),
+    }
+}
+/// Write the time elapsed since, or until, an instant, eg `3 minutes ago` or `in 2 days`.
+pub fn write_relative(w: &mut impl Write, elapsed: chrono::Duration) -> fmt::Result {
+    let secs = elapsed.num_seconds();
+    let (n, unit) = match secs.unsigned_abs() {
+        0..=9 => return w.write_str("just now"),
+        secs @ 10..=59 => (secs, "second"),
+        secs @ 60..=3_599 => (secs / 60, "minute"),
+        secs @ 3_600..=86_399 => (secs / 3_600, "hour"),
+        secs @ 86_400..=2_591_999 => (secs / 86_400, "day"),
+        secs @ 2_592_000..=31_535_999 => (secs / 2_592_000, "month"),
+        secs => (secs / 31_536_000, "year"),
+    };
+    let plural = if n == 1 { "" } else { "s" };
+    if secs < 0 {
+        write!(w, "in {n} {unit}{plural}")
+    } else {
+        write!(w, "{n} {unit}{plural} ago")
+    }
+}
+/// Write an RFC 3339 timestamp, eg `2023-07-05T16:05:00-07:00`, or with a `Z` suffix for UTC.
+pub fn write_iso(w: &mut impl Write, date_time: &chrono::DateTime<FixedOffset>) -> fmt::Result {
+    write!(
+        w,
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        date_time.year(),
+        date_time.month(),
+        date_time.day(),
+        date_time.hour(),
+        date_time.minute(),
+        date_time.second(),
+    )?;
+    let offset_secs = date_time.offset().local_minus_utc();
+    if offset_secs == 0 {
+        return w.write_char('Z');
+    }
+    let sign = if offset_secs < 0 { '-' } else { '+' };
+    let offset_mins = offset_secs.unsigned_abs() / 60;
+    write!(w, "{sign}{:02}:{:02}", offset_mins / 60, offset_mins % 60)
+}
@@ -1,20 +1,29 @@
-use crate::date_time::DateTime;
+pub use crate::fedi::addr::FediAddr;
+use crate::{
+    content::ContentRefs,
+    date_time::{DateTime, DateTimeFormat, DateTimePrefs, TimeZone},
+    fedi::signature::PrivateKey,
+    uuid::{DeliveryId, NotificationId, PostId, RequestId, UserId},
+};
 use async_trait::async_trait;
 use bytesize::ByteSize;
 use clap::Parser;
-use compact_str::{format_compact, CompactString};
-use sailfish::TemplateOnce;
+use compact_str::CompactString;
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use thiserror::Error;
 
 #[derive(Parser, Debug, Default, Clone)]
 pub struct DbConfig {}
 impl DbConfig {
-    /// Return a [`Db`] implementation based on this configuration.
+    /// Return a [`Db`] implementation based on this configuration, for the instance at the given
+    /// host, see [`FediConfig::host`](crate::fedi::FediConfig::host).
     #[allow(unreachable_code)]
-    pub fn init(&self) -> Box<dyn Db> {
+    pub fn init(&self, local_host: &str) -> Box<dyn Db> {
         #[cfg(feature = "dev")]
-        return Box::<crate::dev::db::DevDb>::default();
+        return Box::new(crate::dev::db::DevDb::new(local_host));
+        #[cfg(not(feature = "dev"))]
+        let _ = local_host;
         unimplemented!("no non-dev Dbs yet");
     }
 }
@@ -27,18 +36,243 @@ pub enum DbError {
 }
 #[async_trait]
 pub trait Db: Send + Sync + Debug {
-    async fn posts(&self) -> Result<Vec<Post>>;
+    /// Check the Db is reachable and its schema is up to date, for readiness checks.
+    async fn check(&self) -> Result<()>;
+    /// Flush and close the Db on shutdown, once nothing else will use it.
+    async fn close(&self) -> Result<()>;
+    /// Posts older than the `before` cursor, or the latest if `None`, newest first.
+    async fn posts(&self, before: Option<PostId>, limit: usize) -> Result<Vec<Post>>;
+    async fn post(&self, id: PostId) -> Result<Option<Post>>;
     // NIT: Will need to introduce either alternate methods for in-app vs federated,
     // or simply allow creation of arbitrary sources.
-    async fn create_post(&self, create_post: CreatePost) -> Result<Post>;
+    async fn create_post(&self, create_post: CreatePost, refs: ContentRefs) -> Result<Post>;
+    /// Posts referencing the given normalized tag, newest first.
+    async fn tag_posts(&self, tag: &str, limit: usize) -> Result<Vec<Post>>;
+    /// Look up a local actor, a user or a community, by name.
+    async fn local_actor(&self, name: &str) -> Result<Option<Actor>>;
+    async fn local_user(&self, name: &str) -> Result<Option<LocalUser>>;
+    async fn community(&self, name: &str) -> Result<Option<Community>>;
+    /// The preferences of a local user, or the defaults if never set.
+    async fn preferences(&self, user_id: UserId) -> Result<Preferences>;
+    async fn set_preferences(&self, user_id: UserId, preferences: Preferences) -> Result<()>;
+    /// The profile of a user known to this instance, local or remote.
+    async fn profile(&self, addr: &FediAddr) -> Result<Option<Profile>>;
+    /// Posts by the given author older than the `before` cursor, newest first.
+    async fn user_posts(
+        &self,
+        author: &FediAddr,
+        before: Option<PostId>,
+        limit: usize,
+    ) -> Result<Vec<Post>>;
+    async fn stats(&self) -> Result<Stats>;
+    /// Return the signing key of a local actor, if one has been created.
+    async fn actor_key(&self, actor: &Actor) -> Result<Option<PrivateKey>>;
+    async fn insert_actor_key(&self, actor: &Actor, key: PrivateKey) -> Result<()>;
+    async fn enqueue_delivery(&self, new_delivery: NewDelivery) -> Result<Delivery>;
+    /// Claim up to `limit` pending deliveries due on or before `now`, marking them in flight.
+    async fn claim_deliveries(&self, now: DateTime, limit: usize) -> Result<Vec<Delivery>>;
+    /// Return any in flight deliveries to pending, such as those abandoned by an unclean shutdown.
+    async fn release_deliveries(&self) -> Result<u64>;
+    /// Remove a successful delivery from the queue.
+    async fn complete_delivery(&self, id: DeliveryId) -> Result<()>;
+    /// Record a failed delivery attempt. The delivery is retried on `retry_on`, or marked as
+    /// [`DeliveryState::Failed`] if `None`.
+    async fn fail_delivery(
+        &self,
+        id: DeliveryId,
+        error: String,
+        retry_on: Option<DateTime>,
+    ) -> Result<()>;
+    async fn deliveries(&self, state: DeliveryState) -> Result<Vec<Delivery>>;
+    async fn count_deliveries(&self, state: DeliveryState) -> Result<u64>;
+    async fn delivery_host(&self, host: &str) -> Result<Option<DeliveryHost>>;
+    async fn delivery_hosts(&self) -> Result<Vec<DeliveryHost>>;
+    /// Record the outcome of a delivery attempt to the given host, marking it dead once
+    /// `dead_after` consecutive failures have been reached, and alive again on a success.
+    async fn record_host_attempt(
+        &self,
+        host: &str,
+        success: bool,
+        dead_after: u32,
+    ) -> Result<DeliveryHost>;
+    async fn create_notification(&self, new_notification: NewNotification) -> Result<Notification>;
+    /// Notifications of the given user older than the `before` cursor, newest first.
+    async fn notifications(
+        &self,
+        recipient: UserId,
+        before: Option<NotificationId>,
+        limit: usize,
+    ) -> Result<Vec<Notification>>;
+    async fn unread_notifications(&self, recipient: UserId) -> Result<u64>;
+    /// Mark the given notifications of a user as read, or all of them if `None`.
+    async fn mark_notifications_read(
+        &self,
+        recipient: UserId,
+        ids: Option<&[NotificationId]>,
+    ) -> Result<()>;
+    /// Store an API token of a user, by its hash.
+    async fn insert_api_token(&self, user_id: UserId, token_hash: Vec<u8>) -> Result<()>;
+    /// The user an API token belongs to, by its hash.
+    async fn api_token_user(&self, token_hash: &[u8]) -> Result<Option<LocalUser>>;
+}
+/// Aggregate instance statistics, as reported by NodeInfo and the like.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub users: u64,
+    pub posts: u64,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActorKind {
+    User,
+    Community,
+}
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub kind: ActorKind,
+    pub name: CompactString,
+}
+/// A user registered on this instance.
+#[derive(Debug, Clone)]
+pub struct LocalUser {
+    pub id: UserId,
+    pub name: CompactString,
+    pub joined_on: DateTime,
+    pub display_name: Option<String>,
+    /// Markdown.
+    pub bio: Option<String>,
+    pub avatar_url: Option<String>,
+}
+/// A community hosted on this instance.
+#[derive(Debug, Clone, Serialize)]
+pub struct Community {
+    pub name: CompactString,
+    /// The time zone shown to viewers who haven't chosen their own, for communities with a natural
+    /// time zone such as a city or region.
+    pub time_zone: Option<TimeZone>,
+}
+/// The display preferences of a local user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preferences {
+    /// The chosen time zone, or `None` to use the default of the community or instance.
+    pub time_zone: Option<TimeZone>,
+    pub date_time_format: DateTimeFormat,
+    pub theme: Theme,
+    pub posts_per_page: u32,
+}
+impl Preferences {
+    pub const MAX_POSTS_PER_PAGE: u32 = 100;
+    pub fn date_time(&self) -> DateTimePrefs {
+        self.date_time_in(TimeZone::default())
+    }
+    /// Date time preferences, falling back to the given time zone if none was chosen.
+    pub fn date_time_in(&self, default_tz: TimeZone) -> DateTimePrefs {
+        DateTimePrefs {
+            time_zone: self.time_zone.unwrap_or(default_tz),
+            format: self.date_time_format,
+        }
+    }
+    pub fn posts_per_page(&self) -> usize {
+        self.posts_per_page as usize
+    }
+}
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            time_zone: Default::default(),
+            date_time_format: Default::default(),
+            theme: Default::default(),
+            posts_per_page: 20,
+        }
+    }
+}
+/// The color theme, set as the `data-theme` of pages.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    /// Follow the theme of the browser or OS.
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+impl Theme {
+    pub const ALL: [Self; 3] = [Self::System, Self::Light, Self::Dark];
+    /// The `data-theme` value, empty for [`Theme::System`].
+    pub fn data_theme(&self) -> &'static str {
+        match self {
+            Self::System => "",
+            Self::Light => "light",
+            Self::Dark => "dark",
+        }
+    }
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::System => "system",
+            Self::Light => "light",
+            Self::Dark => "dark",
+        }
+    }
+}
+/// The public profile of a user, local or remote.
+#[derive(Debug, Clone, Serialize)]
+pub struct Profile {
+    pub fedi_addr: FediAddr,
+    pub display_name: Option<String>,
+    /// Markdown.
+    pub bio: Option<String>,
+    pub avatar_url: Option<String>,
+    /// When the user joined, if known. Remote users only report this if their instance does.
+    pub joined_on: Option<DateTime>,
 }
 #[derive(Debug, Clone)]
+pub struct NewDelivery {
+    /// The local actor the delivery is signed by.
+    pub actor: Actor,
+    pub inbox: String,
+    /// The destination host, eg `host:port`, which concurrency and failures are tracked by.
+    pub host: CompactString,
+    pub body: String,
+    /// The request which queued the delivery, sent along to correlate traces across instances.
+    pub request_id: Option<RequestId>,
+}
+/// A queued delivery of an activity to a remote inbox.
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub id: DeliveryId,
+    pub actor: Actor,
+    pub inbox: String,
+    pub host: CompactString,
+    pub body: String,
+    pub request_id: Option<RequestId>,
+    pub state: DeliveryState,
+    pub attempts: u32,
+    pub created_on: DateTime,
+    pub next_attempt_on: DateTime,
+    pub last_error: Option<String>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryState {
+    Pending,
+    InFlight,
+    Failed,
+}
+#[derive(Debug, Default, Clone)]
+pub struct DeliveryHost {
+    pub host: CompactString,
+    pub consecutive_failures: u32,
+    /// When the host was marked dead, or last failed an attempt to revive it. Deliveries to dead
+    /// hosts fail immediately, until the next attempt to revive them.
+    pub dead_since: Option<DateTime>,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Post {
-    // pub id: CompactString,
+    pub id: PostId,
     pub author: Author,
     pub created_on: DateTime,
     pub title: String,
     pub body: String,
+    /// Mentions and tags referenced by the title and body.
+    pub refs: ContentRefs,
 }
 #[derive(Debug, Clone)]
 pub struct CreatePost {
@@ -53,22 +287,44 @@ impl CreatePost {
         ByteSize::b(self.body.len() as u64).to_string_as(true)
     }
 }
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
+pub struct NewNotification {
+    /// The local user being notified.
+    pub recipient: UserId,
+    pub kind: NotificationKind,
+}
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub id: NotificationId,
+    pub recipient: UserId,
+    pub kind: NotificationKind,
+    pub created_on: DateTime,
+    pub read: bool,
+}
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotificationKind {
+    Mention {
+        post: PostId,
+        by: FediAddr,
+    },
+    Reply {
+        post: PostId,
+        by: FediAddr,
+    },
+    /// A post of the recipient reached a milestone number of votes.
+    VoteMilestone {
+        post: PostId,
+        votes: u64,
+    },
+    /// A moderator acted on content of the recipient, eg removing a post.
+    Moderation {
+        action: String,
+        reason: Option<String>,
+    },
+}
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Author {
     // pub id: CompactString,
     pub fedi_addr: FediAddr,
 }
-#[derive(Debug, Default, Clone, TemplateOnce)]
-#[template(path = "component/fedi_addr.stpl")]
-pub struct FediAddr {
-    pub user: CompactString,
-    pub host: CompactString,
-}
-impl FediAddr {
-    pub fn format(&self) -> CompactString {
-        let Self { user, host, .. } = self;
-        // NIT: Should this alter behavior if name or host are missing? Probably only useful for
-        // edgecases like Default or w/e.
-        format_compact!("@{user}@{host}")
-    }
-}
@@ -1,40 +1,447 @@
 use crate::{
-    date_time::DateTime,
-    db::{CreatePost, Db, Post, Result},
+    content::ContentRefs,
+    date_time::{DateTime, TimeZone},
+    db::{
+        Actor, ActorKind, Community, CreatePost, Db, Delivery, DeliveryHost, DeliveryState,
+        FediAddr, LocalUser, NewDelivery, NewNotification, Notification, Post, Preferences,
+        Profile, Result, Stats,
+    },
+    fedi::{signature::PrivateKey, FediConfig},
+    uuid::{DeliveryId, NotificationId, PostId, UserId},
 };
 use anyhow::anyhow;
 use async_trait::async_trait;
-use std::sync::RwLock;
+use compact_str::CompactString;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::RwLock,
+};
+
+/// The community every dev instance starts with, until communities can be created.
+pub const DEV_COMMUNITY: &str = "summit";
 
 #[derive(Debug, Default)]
 pub struct DevDb(RwLock<Inner>);
-#[derive(Debug, Default)]
+impl DevDb {
+    /// Construct an empty Db of the instance at the given host, see [`FediConfig::host`].
+    pub fn new(local_host: &str) -> Self {
+        Self(RwLock::new(Inner {
+            local_host: local_host.into(),
+            ..Default::default()
+        }))
+    }
+}
+#[derive(Debug)]
 struct Inner {
+    /// The host of this instance, whose post authors are its users.
+    local_host: CompactString,
     posts: Vec<Post>,
+    /// Local users are implicitly registered by posting, as there's no sign up yet.
+    users: BTreeMap<CompactString, LocalUser>,
+    communities: BTreeMap<CompactString, Community>,
+    actor_keys: HashMap<(ActorKind, CompactString), PrivateKey>,
+    /// Ordered by id, which being UUIDv7 keeps the queue roughly FIFO.
+    deliveries: BTreeMap<DeliveryId, Delivery>,
+    delivery_hosts: HashMap<CompactString, DeliveryHost>,
+    notifications: Vec<Notification>,
+    preferences: HashMap<UserId, Preferences>,
+    api_tokens: HashMap<Vec<u8>, UserId>,
+}
+impl Default for Inner {
+    fn default() -> Self {
+        Self {
+            local_host: FediConfig::default().host().into(),
+            posts: Default::default(),
+            users: Default::default(),
+            communities: BTreeMap::from([(
+                CompactString::new(DEV_COMMUNITY),
+                Community {
+                    name: DEV_COMMUNITY.into(),
+                    // West coast US, for testing daylight saving time mostly.
+                    time_zone: Some(TimeZone::Named(chrono_tz::America::Los_Angeles)),
+                },
+            )]),
+            actor_keys: Default::default(),
+            deliveries: Default::default(),
+            delivery_hosts: Default::default(),
+            notifications: Default::default(),
+            preferences: Default::default(),
+            api_tokens: Default::default(),
+        }
+    }
 }
 #[async_trait]
 impl Db for DevDb {
-    async fn posts(&self) -> Result<Vec<Post>> {
+    async fn check(&self) -> Result<()> {
+        // In memory, so always reachable and never migrated, short of a poisoned lock.
+        let _db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(())
+    }
+    async fn close(&self) -> Result<()> {
+        // Nothing to flush, its state is lost along with the process.
+        Ok(())
+    }
+    async fn posts(&self, before: Option<PostId>, limit: usize) -> Result<Vec<Post>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db
+            .posts
+            .iter()
+            .rev()
+            .filter(|post| before.map_or(true, |before| post.id < before))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+    async fn post(&self, id: PostId) -> Result<Option<Post>> {
         let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
-        Ok(db.posts.iter().cloned().rev().take(100).collect())
+        Ok(db.posts.iter().find(|post| post.id == id).cloned())
     }
-    async fn create_post(&self, create_post: CreatePost) -> Result<Post> {
+    async fn create_post(&self, create_post: CreatePost, refs: ContentRefs) -> Result<Post> {
         let CreatePost {
             author,
             title,
             body,
         } = create_post;
         let post = Post {
-            // id: "foo".into(),
+            id: PostId::new(),
             created_on: DateTime::now(),
             author,
             title,
             body,
+            refs,
         };
         {
             let mut db = self.0.write().map_err(|_| anyhow!("lock error"))?;
+            let addr = &post.author.fedi_addr;
+            let name = &addr.user;
+            if addr.host.eq_ignore_ascii_case(&db.local_host) && !db.users.contains_key(name) {
+                let user = LocalUser {
+                    id: UserId::new(),
+                    name: name.clone(),
+                    joined_on: post.created_on,
+                    display_name: None,
+                    bio: None,
+                    avatar_url: None,
+                };
+                db.users.insert(name.clone(), user);
+            }
             db.posts.push(post.clone());
         }
         Ok(post)
     }
+    async fn tag_posts(&self, tag: &str, limit: usize) -> Result<Vec<Post>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db
+            .posts
+            .iter()
+            .rev()
+            .filter(|post| post.refs.tags.iter().any(|t| t.as_str() == tag))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+    async fn local_actor(&self, name: &str) -> Result<Option<Actor>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        // Addresses are case insensitive, but fake users are stored as generated.
+        let matches = |candidate: &&CompactString| candidate.eq_ignore_ascii_case(name);
+        let actor = if let Some(name) = db.users.keys().find(matches).cloned() {
+            Actor {
+                kind: ActorKind::User,
+                name,
+            }
+        } else if let Some(name) = db.communities.keys().find(matches).cloned() {
+            Actor {
+                kind: ActorKind::Community,
+                name,
+            }
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(actor))
+    }
+    async fn local_user(&self, name: &str) -> Result<Option<LocalUser>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db
+            .users
+            .values()
+            .find(|user| user.name.eq_ignore_ascii_case(name))
+            .cloned())
+    }
+    async fn community(&self, name: &str) -> Result<Option<Community>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db
+            .communities
+            .values()
+            .find(|community| community.name.eq_ignore_ascii_case(name))
+            .cloned())
+    }
+    async fn preferences(&self, user_id: UserId) -> Result<Preferences> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db.preferences.get(&user_id).copied().unwrap_or_default())
+    }
+    async fn set_preferences(&self, user_id: UserId, preferences: Preferences) -> Result<()> {
+        let mut db = self.0.write().map_err(|_| anyhow!("lock error"))?;
+        db.preferences.insert(user_id, preferences);
+        Ok(())
+    }
+    async fn profile(&self, addr: &FediAddr) -> Result<Option<Profile>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        if !addr.host.eq_ignore_ascii_case(&db.local_host) {
+            // Remote users are only known by their posts.
+            let known = db.posts.iter().any(|post| {
+                let author = &post.author.fedi_addr;
+                author.user.eq_ignore_ascii_case(&addr.user)
+                    && author.host.eq_ignore_ascii_case(&addr.host)
+            });
+            return Ok(known.then(|| Profile {
+                fedi_addr: addr.clone(),
+                display_name: None,
+                bio: None,
+                avatar_url: None,
+                joined_on: None,
+            }));
+        }
+        let Some(user) = db
+            .users
+            .values()
+            .find(|user| user.name.eq_ignore_ascii_case(&addr.user))
+        else {
+            return Ok(None);
+        };
+        Ok(Some(Profile {
+            fedi_addr: FediAddr {
+                user: user.name.clone(),
+                host: addr.host.clone(),
+            },
+            display_name: user.display_name.clone(),
+            bio: user.bio.clone(),
+            avatar_url: user.avatar_url.clone(),
+            joined_on: Some(user.joined_on),
+        }))
+    }
+    async fn user_posts(
+        &self,
+        author: &FediAddr,
+        before: Option<PostId>,
+        limit: usize,
+    ) -> Result<Vec<Post>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db
+            .posts
+            .iter()
+            .rev()
+            .filter(|post| {
+                let addr = &post.author.fedi_addr;
+                addr.user.eq_ignore_ascii_case(&author.user)
+                    && addr.host.eq_ignore_ascii_case(&author.host)
+                    && before.map_or(true, |before| post.id < before)
+            })
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+    async fn stats(&self) -> Result<Stats> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(Stats {
+            users: db.users.len() as u64,
+            posts: db.posts.len() as u64,
+        })
+    }
+    async fn actor_key(&self, actor: &Actor) -> Result<Option<PrivateKey>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db
+            .actor_keys
+            .get(&(actor.kind, actor.name.clone()))
+            .cloned())
+    }
+    async fn insert_actor_key(&self, actor: &Actor, key: PrivateKey) -> Result<()> {
+        let mut db = self.0.write().map_err(|_| anyhow!("lock error"))?;
+        db.actor_keys.insert((actor.kind, actor.name.clone()), key);
+        Ok(())
+    }
+    async fn enqueue_delivery(&self, new_delivery: NewDelivery) -> Result<Delivery> {
+        let NewDelivery {
+            actor,
+            inbox,
+            host,
+            body,
+            request_id,
+        } = new_delivery;
+        let now = DateTime::now();
+        let delivery = Delivery {
+            id: DeliveryId::new(),
+            actor,
+            inbox,
+            host,
+            body,
+            request_id,
+            state: DeliveryState::Pending,
+            attempts: 0,
+            created_on: now,
+            next_attempt_on: now,
+            last_error: None,
+        };
+        let mut db = self.0.write().map_err(|_| anyhow!("lock error"))?;
+        db.deliveries.insert(delivery.id, delivery.clone());
+        Ok(delivery)
+    }
+    async fn claim_deliveries(&self, now: DateTime, limit: usize) -> Result<Vec<Delivery>> {
+        let mut db = self.0.write().map_err(|_| anyhow!("lock error"))?;
+        Ok(db
+            .deliveries
+            .values_mut()
+            .filter(|d| d.state == DeliveryState::Pending && d.next_attempt_on <= now)
+            .take(limit)
+            .map(|d| {
+                d.state = DeliveryState::InFlight;
+                d.clone()
+            })
+            .collect())
+    }
+    async fn release_deliveries(&self) -> Result<u64> {
+        let mut db = self.0.write().map_err(|_| anyhow!("lock error"))?;
+        let mut released = 0;
+        for d in db.deliveries.values_mut() {
+            if d.state == DeliveryState::InFlight {
+                d.state = DeliveryState::Pending;
+                released += 1;
+            }
+        }
+        Ok(released)
+    }
+    async fn complete_delivery(&self, id: DeliveryId) -> Result<()> {
+        let mut db = self.0.write().map_err(|_| anyhow!("lock error"))?;
+        db.deliveries.remove(&id);
+        Ok(())
+    }
+    async fn fail_delivery(
+        &self,
+        id: DeliveryId,
+        error: String,
+        retry_on: Option<DateTime>,
+    ) -> Result<()> {
+        let mut db = self.0.write().map_err(|_| anyhow!("lock error"))?;
+        let d = db
+            .deliveries
+            .get_mut(&id)
+            .ok_or_else(|| anyhow!("delivery not found: {id}"))?;
+        d.attempts += 1;
+        d.last_error = Some(error);
+        match retry_on {
+            Some(retry_on) => {
+                d.state = DeliveryState::Pending;
+                d.next_attempt_on = retry_on;
+            },
+            None => d.state = DeliveryState::Failed,
+        }
+        Ok(())
+    }
+    async fn deliveries(&self, state: DeliveryState) -> Result<Vec<Delivery>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db
+            .deliveries
+            .values()
+            .filter(|d| d.state == state)
+            .cloned()
+            .collect())
+    }
+    async fn count_deliveries(&self, state: DeliveryState) -> Result<u64> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db.deliveries.values().filter(|d| d.state == state).count() as u64)
+    }
+    async fn delivery_host(&self, host: &str) -> Result<Option<DeliveryHost>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db.delivery_hosts.get(host).cloned())
+    }
+    async fn delivery_hosts(&self) -> Result<Vec<DeliveryHost>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db.delivery_hosts.values().cloned().collect())
+    }
+    async fn record_host_attempt(
+        &self,
+        host: &str,
+        success: bool,
+        dead_after: u32,
+    ) -> Result<DeliveryHost> {
+        let mut db = self.0.write().map_err(|_| anyhow!("lock error"))?;
+        let entry = db
+            .delivery_hosts
+            .entry(host.into())
+            .or_insert_with(|| DeliveryHost {
+                host: host.into(),
+                ..Default::default()
+            });
+        if success {
+            entry.consecutive_failures = 0;
+            entry.dead_since = None;
+        } else {
+            entry.consecutive_failures += 1;
+            if entry.consecutive_failures >= dead_after {
+                entry.dead_since = Some(DateTime::now());
+            }
+        }
+        Ok(entry.clone())
+    }
+    async fn create_notification(&self, new_notification: NewNotification) -> Result<Notification> {
+        let NewNotification { recipient, kind } = new_notification;
+        let notification = Notification {
+            id: NotificationId::new(),
+            recipient,
+            kind,
+            created_on: DateTime::now(),
+            read: false,
+        };
+        let mut db = self.0.write().map_err(|_| anyhow!("lock error"))?;
+        db.notifications.push(notification.clone());
+        Ok(notification)
+    }
+    async fn notifications(
+        &self,
+        recipient: UserId,
+        before: Option<NotificationId>,
+        limit: usize,
+    ) -> Result<Vec<Notification>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db
+            .notifications
+            .iter()
+            .rev()
+            .filter(|n| n.recipient == recipient && before.map_or(true, |before| n.id < before))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+    async fn unread_notifications(&self, recipient: UserId) -> Result<u64> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        Ok(db
+            .notifications
+            .iter()
+            .filter(|n| n.recipient == recipient && !n.read)
+            .count() as u64)
+    }
+    async fn mark_notifications_read(
+        &self,
+        recipient: UserId,
+        ids: Option<&[NotificationId]>,
+    ) -> Result<()> {
+        let mut db = self.0.write().map_err(|_| anyhow!("lock error"))?;
+        for n in db.notifications.iter_mut() {
+            if n.recipient == recipient && ids.map_or(true, |ids| ids.contains(&n.id)) {
+                n.read = true;
+            }
+        }
+        Ok(())
+    }
+    async fn insert_api_token(&self, user_id: UserId, token_hash: Vec<u8>) -> Result<()> {
+        let mut db = self.0.write().map_err(|_| anyhow!("lock error"))?;
+        db.api_tokens.insert(token_hash, user_id);
+        Ok(())
+    }
+    async fn api_token_user(&self, token_hash: &[u8]) -> Result<Option<LocalUser>> {
+        let db = self.0.read().map_err(|_| anyhow!("lock error"))?;
+        let Some(&user_id) = db.api_tokens.get(token_hash) else {
+            return Ok(None);
+        };
+        Ok(db.users.values().find(|user| user.id == user_id).cloned())
+    }
 }
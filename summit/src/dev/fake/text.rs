@@ -133,11 +133,13 @@ impl Dummy<FediHostName> for CompactString {
             .choose(rng)
             .map(|s| s.to_lowercase())
             .unwrap_or(String::from("example"));
-        let host_name = Faker
-            .fake_with_rng::<bool, _>(rng)
-            .then(|| NameJoinStyle::AllLowerHyphen)
-            .unwrap_or(NameJoinStyle::AllLowerUnderscore)
-            .join(&[adj, noun], rng);
+        // Underscores aren't valid in host names, so would fail to parse as a `FediAddr`.
+        let join_style = if Faker.fake_with_rng::<bool, _>(rng) {
+            NameJoinStyle::AllLowerHyphen
+        } else {
+            NameJoinStyle::AllLowercase
+        };
+        let host_name = join_style.join(&[adj, noun], rng);
         format_compact!("{host_name}.{suffix}")
     }
 }
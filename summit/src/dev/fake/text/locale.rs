@@ -174,8 +174,8 @@ where
             let words = locale.words();
             iter::from_fn(|| Some(3..15))
                 .take(sent_limit)
-                .flat_map(|iter| iter)
-                .map(|_| words.choose(rng).clone())
+                .flatten()
+                .map(|_| words.choose(rng))
                 .map_while(|word_opt| word_opt)
                 .map(|word| word.to_string())
                 .collect()
@@ -200,7 +200,7 @@ impl Dummy<Sent> for String {
                 (_, SentFrag::Word) | (_, _) => {
                     // This is pretty crude, but works for now.
                     if i > 0 {
-                        buf.push_str(" ");
+                        buf.push(' ');
                     }
                     // TODO: Hook up to Locale.
                     //
@@ -213,7 +213,7 @@ impl Dummy<Sent> for String {
             prev_frag = frag;
         }
         // TODO: Add this to punc type to the locale trait? Maybe generalized?
-        buf.push_str(".");
+        buf.push('.');
         buf
     }
 }
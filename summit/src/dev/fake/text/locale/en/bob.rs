@@ -3,7 +3,7 @@ use crate::dev::fake::text::locale::LocaleText;
 use super::EnLorem;
 
 /// Compiled by https://www.bobrosslipsum.com 2023 July
-const BOB_SENTENCE_WORDS: [&'static [&'static str]; 403] = [
+const BOB_SENTENCE_WORDS: [&[&str]; 403] = [
     &["A", "beautiful", "little", "sunset."],
     &[
         "A", "big", "strong", "tree", "needs", "big", "strong", "roots.",
@@ -2710,7 +2710,7 @@ const BOB_SENTENCE_WORDS: [&'static [&'static str]; 403] = [
     ],
     &["Zip.", "That", "easy."],
 ];
-const BOB_ADJECTIVES: [&'static str; 49] = [
+const BOB_ADJECTIVES: [&str; 49] = [
     "Peaceful",
     "Tranquil",
     "Serene",
@@ -2761,7 +2761,7 @@ const BOB_ADJECTIVES: [&'static str; 49] = [
     "Midnight",
     "Scented",
 ];
-const BOB_NOUNS: [&'static str; 49] = [
+const BOB_NOUNS: [&str; 49] = [
     "Cloud",
     "Meadow",
     "Sunset",
@@ -2812,7 +2812,7 @@ const BOB_NOUNS: [&'static str; 49] = [
     "Heath",
     "Field",
 ];
-const BOB_VERBS: [&'static str; 49] = [
+const BOB_VERBS: [&str; 49] = [
     "Whisper", "Glow", "Cascade", "Flow", "Drift", "Shimmer", "Glisten", "Reflect", "Bloom",
     "Sprout", "Swirl", "Sway", "Ripple", "Dance", "Twinkle", "Sparkle", "Murmur", "Gleam",
     "Quiver", "Flutter", "Glide", "Wander", "Soar", "Drizzle", "Shine", "Breathe", "Blossom",
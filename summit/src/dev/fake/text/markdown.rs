@@ -26,7 +26,7 @@ pub enum WordMarkup {
 impl WordMarkup {
     pub fn format_string(&self, s: &mut String) {
         match self {
-            WordMarkup::None => return,
+            WordMarkup::None => (),
             WordMarkup::ItalicStar => *s = format!("*{}*", &s),
             WordMarkup::ItalicUnderscore => *s = format!("_{}_", &s),
             WordMarkup::BoldStar => *s = format!("**{}**", &s),
@@ -146,7 +146,7 @@ where
         let limit: usize = range.fake_with_rng(rng);
         iter::from_fn(|| Some(Sentence::locale(locale).fake_with_rng::<Vec<String>, Rng>(rng)))
             .take(limit)
-            .flat_map(|words| words)
+            .flatten()
             .collect()
     }
 }
@@ -30,7 +30,9 @@ pub struct FakeUserRt<R> {
 impl<R: rand::Rng> FakeUserRt<R> {
     /// Construct a new user with fake parameters generated from the given seed.
     pub fn new(mut rng: R, summit: Arc<Summit>, new_fake_user: NewFakeUser) -> Self {
-        let fake_user: FakeUser = new_fake_user.fake_with_rng(&mut rng);
+        let mut fake_user: FakeUser = new_fake_user.fake_with_rng(&mut rng);
+        // Fake users post on this instance, so are its users, whichever host was faked.
+        fake_user.user.fedi_addr.host = summit.fedi_config().host().into();
         let next_tick = fake_user.tick_rate;
         Self {
             rng,
@@ -47,7 +49,7 @@ impl<R: rand::Rng> FakeUserRt<R> {
         if let Err(err) = self.action().await {
             warn!(?err, "encountered error faking user action");
         }
-        return Ok(());
+        Ok(())
     }
     async fn action(&mut self) -> crate::Result<()> {
         self.summit
@@ -91,8 +93,8 @@ impl Dummy<NewFakeUser> for FakeUser {
                 // +1 to one-index the math.
                 5.0 * (config.fake_user_index + 1) as f32,
             );
-        let tick_rate = (rate_of_actions_secs as f32 * 1000. / config.config.tick_dur.max(1) as f32)
-            .round() as u64;
+        let tick_rate =
+            (rate_of_actions_secs * 1000. / config.config.tick_dur.max(1) as f32).round() as u64;
         Self {
             locale,
             user,
@@ -1,5 +1,5 @@
 use super::user::FakeUserRt;
-use crate::Summit;
+use crate::{web::shutdown::ShutdownSignal, Summit};
 use anyhow::anyhow;
 use clap::Parser;
 use fake::{Fake, Faker};
@@ -8,7 +8,7 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
-use tokio::sync::Mutex;
+use tokio::{sync::Mutex, task::JoinHandle};
 use tracing::{debug_span, error, info, warn, Instrument};
 
 #[derive(Parser, Debug, Default, Clone)]
@@ -47,15 +47,20 @@ pub struct FakeUserInitConfig {
     pub dont_start_runtime: bool,
 }
 impl FakeUserInitConfig {
-    /// Initialize fake users over the given Summit instance.
-    //
-    // TODO: Include stop channel.
-    pub async fn init(&self, summit: Arc<Summit>) -> Arc<FakeUsers> {
-        let f = Arc::new(FakeUsers::new(summit, &self));
+    /// Initialize fake users over the given Summit instance, running until shutdown.
+    ///
+    /// Returns the task creating and running the users, if any, to wait on during shutdown.
+    pub async fn init(
+        &self,
+        summit: Arc<Summit>,
+        shutdown_signal: ShutdownSignal,
+    ) -> (Arc<FakeUsers>, Option<JoinHandle<()>>) {
+        let f = Arc::new(FakeUsers::new(summit, self));
+        let mut runtime = None;
         if self.fake_count > 0 {
             let config = self.clone();
             let f = Arc::clone(&f);
-            tokio::spawn(async move {
+            runtime = Some(tokio::spawn(async move {
                 tokio::time::sleep(Duration::from_millis(config.start_on_init_delay_ms)).await;
                 for fake_user_index in 0..config.fake_count {
                     if let Err(err) = f.new_user().await {
@@ -71,15 +76,15 @@ impl FakeUserInitConfig {
                     error!(?err, "advancing fake user runtime failed");
                 }
                 if !config.dont_start_runtime {
-                    if let Err(err) = f.start_runtime(config.tick_dur).await {
+                    if let Err(err) = f.start_runtime(config.tick_dur, shutdown_signal).await {
                         error!(?err, "fake user runtime exited");
                     } else {
-                        error!("fake user runtime exited");
+                        info!("fake user runtime stopped");
                     }
                 }
-            });
+            }));
         }
-        f
+        (f, runtime)
     }
 }
 
@@ -120,19 +125,32 @@ impl FakeUsers {
         Ok(())
     }
     // NIT: Make this runtime move..? I don't want it to start twice, iirc it was shared to pass
-    // state to the web server for remote control, but a shutdown channel is probably all that's
-    // necesary.
-    pub async fn start_runtime(&self, tick_rate_ms: u64) -> anyhow::Result<()> {
+    // state to the web server for remote control.
+    /// Tick the fake users until shutdown, finishing the current tick first.
+    pub async fn start_runtime(
+        &self,
+        tick_rate_ms: u64,
+        shutdown_signal: ShutdownSignal,
+    ) -> anyhow::Result<()> {
         warn!(tick_rate_ms, "starting fake user runtime");
         let tick_rate = Duration::from_millis(tick_rate_ms);
+        let summit = Arc::clone(&self.0.lock().await.summit);
         let mut prev_tick = Instant::now();
         for tick in 0.. {
+            if shutdown_signal.is_shutdown() {
+                break;
+            }
             let res: anyhow::Result<()> = async {
                 self.0.lock().await.tick_users(tick).await?;
                 let now = Instant::now();
                 let elapsed = now.duration_since(prev_tick);
+                let lag = elapsed.saturating_sub(tick_rate);
+                summit.metrics().fake_tick_lag.set(lag.as_secs_f64());
                 if let Some(wait_for) = tick_rate.checked_sub(elapsed) {
-                    tokio::time::sleep(wait_for).await;
+                    tokio::select! {
+                        _ = tokio::time::sleep(wait_for) => {},
+                        () = shutdown_signal.recv() => {},
+                    }
                 }
                 prev_tick = now;
                 Ok(())
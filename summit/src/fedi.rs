@@ -0,0 +1,76 @@
+//! Federation, the glue between Summit and the rest of the fediverse.
+use self::signature::KeyAlgorithm;
+use crate::db::{Actor, ActorKind};
+use clap::Parser;
+use http::Uri;
+use std::time::Duration;
+
+pub mod addr;
+pub mod delivery;
+pub mod signature;
+
+#[derive(Parser, Debug, Clone)]
+pub struct FediConfig {
+    /// The public facing url of this instance. Used to build actor links, and as the host of local
+    /// fedi addresses.
+    #[arg(long, default_value = "http://localhost:3000")]
+    pub public_url: Uri,
+    /// The algorithm used when generating keys for local actors.
+    #[arg(long = "fedi-key-algorithm", value_enum, default_value_t = KeyAlgorithm::Rsa)]
+    pub key_algorithm: KeyAlgorithm,
+    /// The maximum difference allowed between the date of a signed request and our own clock.
+    ///
+    /// In seconds.
+    #[arg(long = "fedi-max-clock-skew", default_value_t = 300)]
+    pub max_clock_skew_secs: u64,
+    /// How long fetched remote actor keys are cached before being fetched again.
+    ///
+    /// In seconds.
+    #[arg(long = "fedi-remote-key-ttl", default_value_t = 3600)]
+    pub remote_key_ttl_secs: u64,
+}
+impl Default for FediConfig {
+    fn default() -> Self {
+        Self {
+            public_url: Uri::from_static("http://localhost:3000"),
+            key_algorithm: KeyAlgorithm::Rsa,
+            max_clock_skew_secs: 300,
+            remote_key_ttl_secs: 3600,
+        }
+    }
+}
+impl FediConfig {
+    /// The host, and port if any, of this instance. Eg the `host` in `@user@host`.
+    pub fn host(&self) -> &str {
+        self.public_url
+            .authority()
+            .map_or("localhost", |authority| authority.as_str())
+    }
+    /// Return an absolute url to the given path on this instance.
+    pub fn url(&self, path: &str) -> String {
+        let scheme = self.public_url.scheme_str().unwrap_or("https");
+        format!("{scheme}://{}{path}", self.host())
+    }
+    pub fn user_url(&self, name: &str) -> String {
+        self.url(&format!("/u/{name}"))
+    }
+    pub fn community_url(&self, name: &str) -> String {
+        self.url(&format!("/c/{name}"))
+    }
+    pub fn actor_url(&self, actor: &Actor) -> String {
+        match actor.kind {
+            ActorKind::User => self.user_url(&actor.name),
+            ActorKind::Community => self.community_url(&actor.name),
+        }
+    }
+    /// The `keyId` of the given actor's signing key.
+    pub fn key_id(&self, actor: &Actor) -> String {
+        format!("{}#main-key", self.actor_url(actor))
+    }
+    pub fn max_clock_skew(&self) -> Duration {
+        Duration::from_secs(self.max_clock_skew_secs)
+    }
+    pub fn remote_key_ttl(&self) -> Duration {
+        Duration::from_secs(self.remote_key_ttl_secs)
+    }
+}
@@ -0,0 +1,210 @@
+//! [`FediAddr`], the `@user@host` address of an actor on the fediverse.
+use compact_str::{format_compact, CompactString};
+use sailfish::TemplateOnce;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+/// The maximum length of the user portion of an address, matching common implementations.
+pub const MAX_USER_LEN: usize = 64;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum FediAddrError {
+    #[error("missing `@` between user and host")]
+    MissingSeparator,
+    #[error("empty user")]
+    EmptyUser,
+    #[error("user longer than {MAX_USER_LEN} characters")]
+    UserTooLong,
+    #[error("invalid character in user: {0:?}")]
+    InvalidUserChar(char),
+    #[error("user must not start or end with `.` or `-`")]
+    InvalidUserBoundary,
+    #[error("empty host")]
+    EmptyHost,
+    #[error("invalid host: {0}")]
+    InvalidHost(CompactString),
+    #[error("invalid port: {0}")]
+    InvalidPort(CompactString),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, TemplateOnce)]
+#[template(path = "component/fedi_addr.stpl")]
+pub struct FediAddr {
+    pub user: CompactString,
+    pub host: CompactString,
+}
+impl FediAddr {
+    /// Parse an address in any of the `@user@host`, `user@host` or `acct:user@host` forms.
+    ///
+    /// The user and host are validated and lowercased, with internationalized hosts converted to
+    /// their punycode form. The host may include a port.
+    pub fn parse(s: &str) -> Result<Self, FediAddrError> {
+        let s = s.trim();
+        let s = s.strip_prefix("acct:").unwrap_or(s);
+        let s = s.strip_prefix('@').unwrap_or(s);
+        let (user, host) = s.split_once('@').ok_or(FediAddrError::MissingSeparator)?;
+        Ok(Self {
+            user: parse_user(user)?,
+            host: parse_host(host)?,
+        })
+    }
+    /// Parse an address as [`Self::parse`], or a bare `user` as a user of `local_host`.
+    pub fn parse_or_local(s: &str, local_host: &str) -> Result<Self, FediAddrError> {
+        if s.contains('@') {
+            Self::parse(s)
+        } else {
+            Self::parse(&format!("{s}@{local_host}"))
+        }
+    }
+    pub fn format(&self) -> CompactString {
+        let Self { user, host, .. } = self;
+        // NOTE: Parsed addresses always have both a user and host, so only hand built or Default
+        // addresses may render oddly here.
+        format_compact!("@{user}@{host}")
+    }
+    /// Format as an `acct:` URI, as used by WebFinger.
+    pub fn acct(&self) -> CompactString {
+        let Self { user, host, .. } = self;
+        format_compact!("acct:{user}@{host}")
+    }
+}
+impl FromStr for FediAddr {
+    type Err = FediAddrError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+impl TryFrom<&str> for FediAddr {
+    type Error = FediAddrError;
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::parse(s)
+    }
+}
+impl fmt::Display for FediAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}@{}", self.user, self.host)
+    }
+}
+
+fn parse_user(user: &str) -> Result<CompactString, FediAddrError> {
+    if user.is_empty() {
+        return Err(FediAddrError::EmptyUser);
+    }
+    if user.chars().count() > MAX_USER_LEN {
+        return Err(FediAddrError::UserTooLong);
+    }
+    if let Some(c) = user
+        .chars()
+        .find(|&c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-')))
+    {
+        return Err(FediAddrError::InvalidUserChar(c));
+    }
+    if user.starts_with(['.', '-']) || user.ends_with(['.', '-']) {
+        return Err(FediAddrError::InvalidUserBoundary);
+    }
+    Ok(user.to_ascii_lowercase().into())
+}
+fn parse_host(host: &str) -> Result<CompactString, FediAddrError> {
+    if host.is_empty() {
+        return Err(FediAddrError::EmptyHost);
+    }
+    let (domain, port) = match host.rsplit_once(':') {
+        Some((domain, port)) => (domain, Some(port)),
+        None => (host, None),
+    };
+    // Strict, so that the usual DNS label and length rules are enforced alongside IDNA.
+    let domain = idna::domain_to_ascii_strict(domain)
+        .ok()
+        .filter(|domain| !domain.is_empty())
+        .ok_or_else(|| FediAddrError::InvalidHost(domain.into()))?;
+    match port {
+        Some(port) => {
+            let port = port
+                .parse::<u16>()
+                .ok()
+                .filter(|&port| port != 0)
+                .ok_or_else(|| FediAddrError::InvalidPort(port.into()))?;
+            Ok(format_compact!("{domain}:{port}"))
+        },
+        None => Ok(domain.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn addr(user: &str, host: &str) -> FediAddr {
+        FediAddr {
+            user: user.into(),
+            host: host.into(),
+        }
+    }
+
+    #[test]
+    fn parse_forms() {
+        let expected = addr("alice", "summit.example");
+        assert_eq!("@alice@summit.example".parse(), Ok(expected.clone()));
+        assert_eq!("alice@summit.example".parse(), Ok(expected.clone()));
+        assert_eq!("acct:alice@summit.example".parse(), Ok(expected.clone()));
+        assert_eq!(FediAddr::try_from(" @Alice@Summit.Example "), Ok(expected));
+        assert_eq!(
+            "bob@localhost:3000".parse(),
+            Ok(addr("bob", "localhost:3000"))
+        );
+    }
+    #[test]
+    fn parse_or_local() {
+        assert_eq!(
+            FediAddr::parse_or_local("alice", "summit.example"),
+            Ok(addr("alice", "summit.example"))
+        );
+        assert_eq!(
+            FediAddr::parse_or_local("bob@remote.example", "summit.example"),
+            Ok(addr("bob", "remote.example"))
+        );
+        assert_eq!(
+            FediAddr::parse_or_local("a lice", "summit.example"),
+            Err(FediAddrError::InvalidUserChar(' '))
+        );
+    }
+    #[test]
+    fn parse_idna() {
+        assert_eq!(
+            "carol@bücher.example".parse(),
+            Ok(addr("carol", "xn--bcher-kva.example"))
+        );
+    }
+    #[test]
+    fn parse_errors() {
+        let err = |s: &str| s.parse::<FediAddr>().unwrap_err();
+        assert_eq!(err("alice"), FediAddrError::MissingSeparator);
+        assert_eq!(err("@@host.example"), FediAddrError::EmptyUser);
+        assert_eq!(err("alice@"), FediAddrError::EmptyHost);
+        assert_eq!(
+            err("al ice@host.example"),
+            FediAddrError::InvalidUserChar(' ')
+        );
+        assert_eq!(
+            err(".alice@host.example"),
+            FediAddrError::InvalidUserBoundary
+        );
+        assert!(matches!(
+            err("alice@bad_host.example"),
+            FediAddrError::InvalidHost(_)
+        ));
+        assert!(matches!(
+            err("alice@a@b.example"),
+            FediAddrError::InvalidHost(_)
+        ));
+        assert!(matches!(
+            err("alice@host.example:0"),
+            FediAddrError::InvalidPort(_)
+        ));
+        assert_eq!(
+            err(&format!("{}@host.example", "a".repeat(65))),
+            FediAddrError::UserTooLong
+        );
+    }
+}
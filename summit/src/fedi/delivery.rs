@@ -0,0 +1,515 @@
+//! The outbound federation delivery queue.
+//!
+//! Deliveries are persisted in the [`Db`](crate::db::Db) before being attempted, and retried with
+//! exponential backoff until they succeed, permanently fail, or their destination host is
+//! considered dead. Dead hosts are tried again after a cooldown, and revived if that succeeds.
+use crate::{
+    date_time::DateTime,
+    db::{Delivery, DeliveryState},
+    web::{
+        extension::request_id::REQUEST_ID_HEADER, handler::webfinger::ACTIVITY_JSON_CONTENT_TYPE,
+        shutdown::ShutdownSignal,
+    },
+    Summit,
+};
+use anyhow::anyhow;
+use clap::Parser;
+use compact_str::CompactString;
+use http::{header, StatusCode};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{sync::Semaphore, task::JoinHandle};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+#[derive(Parser, Debug, Clone)]
+pub struct DeliveryConfig {
+    /// The maximum number of concurrent deliveries to any single destination host.
+    #[arg(long = "delivery-host-concurrency", default_value_t = 2)]
+    pub host_concurrency: usize,
+    /// The number of attempts made before a delivery is marked as failed.
+    #[arg(long = "delivery-max-attempts", default_value_t = 10)]
+    pub max_attempts: u32,
+    /// The delay before the first retry, doubling with each subsequent retry.
+    ///
+    /// In milliseconds.
+    #[arg(long = "delivery-backoff", default_value_t = 30_000)]
+    pub backoff_ms: u64,
+    /// The upper bound of the delay between retries.
+    ///
+    /// In milliseconds.
+    #[arg(long = "delivery-max-backoff", default_value_t = 6 * 60 * 60 * 1000)]
+    pub max_backoff_ms: u64,
+    /// The number of consecutive failures after which a host is marked dead, and no longer
+    /// delivered to.
+    #[arg(long = "delivery-dead-host-after", default_value_t = 50)]
+    pub dead_host_after: u32,
+    /// How long a dead host is left before a delivery to it is attempted again, reviving the
+    /// host if it succeeds.
+    ///
+    /// In milliseconds.
+    #[arg(long = "delivery-dead-host-cooldown", default_value_t = 24 * 60 * 60 * 1000)]
+    pub dead_host_cooldown_ms: u64,
+    /// How often the queue is checked for due deliveries.
+    ///
+    /// In milliseconds.
+    #[arg(long = "delivery-poll", default_value_t = 1000)]
+    pub poll_ms: u64,
+    /// The maximum number of deliveries claimed per poll.
+    #[arg(long = "delivery-batch", default_value_t = 100)]
+    pub batch_size: usize,
+    /// The timeout of a single delivery attempt.
+    ///
+    /// In milliseconds.
+    #[arg(long = "delivery-timeout", default_value_t = 30_000)]
+    pub timeout_ms: u64,
+}
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        Self {
+            host_concurrency: 2,
+            max_attempts: 10,
+            backoff_ms: 30_000,
+            max_backoff_ms: 6 * 60 * 60 * 1000,
+            dead_host_after: 50,
+            dead_host_cooldown_ms: 24 * 60 * 60 * 1000,
+            poll_ms: 1000,
+            batch_size: 100,
+            timeout_ms: 30_000,
+        }
+    }
+}
+impl DeliveryConfig {
+    /// The delay before retrying a delivery that has failed `attempts` times.
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempts.saturating_sub(1));
+        Duration::from_millis(
+            self.backoff_ms
+                .saturating_mul(factor)
+                .min(self.max_backoff_ms),
+        )
+    }
+}
+
+/// The outcome of a single delivery attempt.
+#[derive(Debug)]
+enum Attempt {
+    Delivered,
+    /// A failure worth retrying, such as a timeout or server error.
+    Retry(anyhow::Error),
+    /// A failure that retrying won't fix, such as the remote rejecting the activity.
+    Reject(anyhow::Error),
+}
+
+/// The number of polls the queue may miss before it's considered stalled.
+const STALLED_AFTER_POLLS: u32 = 5;
+
+#[derive(Debug)]
+pub struct DeliveryQueue {
+    config: DeliveryConfig,
+    host_limits: Mutex<HashMap<CompactString, Arc<Semaphore>>>,
+    /// When the queue was last polled, `None` until it first runs.
+    last_poll: Mutex<Option<Instant>>,
+}
+impl DeliveryQueue {
+    pub fn new(config: DeliveryConfig) -> Self {
+        Self {
+            config,
+            host_limits: Default::default(),
+            last_poll: Default::default(),
+        }
+    }
+    /// Whether the queue is running, having polled recently.
+    pub fn is_alive(&self) -> bool {
+        let stalled_after = Duration::from_millis(self.config.poll_ms) * STALLED_AFTER_POLLS;
+        self.last_poll
+            .lock()
+            .ok()
+            .and_then(|last_poll| *last_poll)
+            .map_or(false, |last_poll| last_poll.elapsed() < stalled_after)
+    }
+    pub fn config(&self) -> &DeliveryConfig {
+        &self.config
+    }
+    /// Run the queue until shutdown, polling for due deliveries, then wait on the attempts still
+    /// in flight for up to `shutdown_timeout`. Those which don't finish in time are aborted, so
+    /// that none finishes after [`Summit::close`] releases its delivery.
+    pub async fn run(
+        summit: Arc<Summit>,
+        shutdown_signal: ShutdownSignal,
+        shutdown_timeout: Duration,
+    ) {
+        let queue = summit.delivery_queue();
+        match summit.db.release_deliveries().await {
+            Ok(0) => {},
+            Ok(released) => info!(released, "released abandoned in flight deliveries"),
+            Err(err) => error!(?err, "failed to release in flight deliveries"),
+        }
+        let mut interval = tokio::time::interval(Duration::from_millis(queue.config.poll_ms));
+        // Attempts run independently of the poll, and are only awaited on shutdown.
+        let mut in_flight = Vec::new();
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                () = shutdown_signal.recv() => break,
+            }
+            in_flight.retain(|handle: &JoinHandle<()>| !handle.is_finished());
+            match queue.process_due(&summit).await {
+                Ok(handles) => in_flight.extend(handles),
+                Err(err) => error!(?err, "failed to process delivery queue"),
+            }
+        }
+        in_flight.retain(|handle| !handle.is_finished());
+        info!(
+            in_flight = in_flight.len(),
+            "delivery queue stopped, finishing in flight attempts"
+        );
+        let finished = tokio::time::timeout(
+            shutdown_timeout,
+            futures::future::join_all(in_flight.iter_mut()),
+        )
+        .await;
+        if finished.is_err() {
+            in_flight.retain(|handle| !handle.is_finished());
+            warn!(
+                ?shutdown_timeout,
+                in_flight = in_flight.len(),
+                "aborting in flight attempts after shutdown timeout"
+            );
+            for handle in &in_flight {
+                handle.abort();
+            }
+            futures::future::join_all(in_flight).await;
+        }
+    }
+    /// Claim all due deliveries and spawn an attempt for each, returning the spawned tasks.
+    pub async fn process_due(&self, summit: &Arc<Summit>) -> crate::Result<Vec<JoinHandle<()>>> {
+        if let Ok(mut last_poll) = self.last_poll.lock() {
+            *last_poll = Some(Instant::now());
+        }
+        self.evict_idle_host_limits()?;
+        let due = summit
+            .db
+            .claim_deliveries(DateTime::now(), self.config.batch_size)
+            .await?;
+        let mut handles = Vec::with_capacity(due.len());
+        for delivery in due {
+            let summit = Arc::clone(summit);
+            let host_limit = self.host_limit(&delivery.host)?;
+            let span = info_span!("delivery", id = %delivery.id, host = %delivery.host);
+            handles.push(tokio::spawn(
+                async move {
+                    let _permit = host_limit.acquire_owned().await;
+                    if let Err(err) = summit.delivery_queue().attempt(&summit, delivery).await {
+                        error!(?err, "failed to record delivery attempt");
+                    }
+                }
+                .instrument(span),
+            ));
+        }
+        Ok(handles)
+    }
+    fn host_limit(&self, host: &str) -> crate::Result<Arc<Semaphore>> {
+        let mut host_limits = self.host_limits.lock().map_err(|err| anyhow!("{err}"))?;
+        let limit = host_limits
+            .entry(host.into())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.config.host_concurrency.max(1))));
+        Ok(Arc::clone(limit))
+    }
+    /// Remove the limits of hosts without attempts, which hold the only other references.
+    fn evict_idle_host_limits(&self) -> crate::Result<()> {
+        let mut host_limits = self.host_limits.lock().map_err(|err| anyhow!("{err}"))?;
+        host_limits.retain(|_, limit| Arc::strong_count(limit) > 1);
+        Ok(())
+    }
+    async fn attempt(&self, summit: &Summit, delivery: Delivery) -> crate::Result<()> {
+        let db = &summit.db;
+        let dead_since = db
+            .delivery_host(&delivery.host)
+            .await?
+            .and_then(|host| host.dead_since);
+        if let Some(dead_since) = dead_since {
+            let cooldown = Duration::from_millis(self.config.dead_host_cooldown_ms);
+            if DateTime::now() < dead_since + cooldown {
+                warn!("destination host is dead, failing delivery");
+                db.fail_delivery(delivery.id, "destination host is dead".into(), None)
+                    .await?;
+                return Ok(());
+            }
+            info!("destination host is dead, attempting to revive it");
+        }
+        let attempt = self.send(summit, &delivery).await;
+        let host_ok = !matches!(attempt, Attempt::Retry(_));
+        let host = db
+            .record_host_attempt(&delivery.host, host_ok, self.config.dead_host_after)
+            .await?;
+        if !host_ok && host.consecutive_failures == self.config.dead_host_after {
+            warn!(
+                consecutive_failures = host.consecutive_failures,
+                "destination host marked dead"
+            );
+        } else if host_ok && dead_since.is_some() {
+            info!("destination host revived");
+        }
+        match attempt {
+            Attempt::Delivered => {
+                debug!("delivered");
+                db.complete_delivery(delivery.id).await?;
+            },
+            Attempt::Retry(err) if delivery.attempts + 1 < self.config.max_attempts => {
+                let backoff = self.config.backoff(delivery.attempts + 1);
+                debug!(?err, ?backoff, "delivery failed, retrying");
+                db.fail_delivery(
+                    delivery.id,
+                    err.to_string(),
+                    Some(DateTime::now() + backoff),
+                )
+                .await?;
+            },
+            Attempt::Retry(err) | Attempt::Reject(err) => {
+                warn!(?err, attempts = delivery.attempts + 1, "delivery failed");
+                db.fail_delivery(delivery.id, err.to_string(), None).await?;
+            },
+        }
+        Ok(())
+    }
+    async fn send(&self, summit: &Summit, delivery: &Delivery) -> Attempt {
+        let mut request = summit
+            .http_client()
+            .post(&delivery.inbox)
+            .header(header::CONTENT_TYPE, ACTIVITY_JSON_CONTENT_TYPE)
+            .timeout(Duration::from_millis(self.config.timeout_ms))
+            .body(delivery.body.clone());
+        if let Some(request_id) = delivery.request_id {
+            request = request.header(REQUEST_ID_HEADER, request_id.to_string());
+        }
+        let request = request.build();
+        let mut request = match request {
+            Ok(request) => request,
+            Err(err) => return Attempt::Reject(err.into()),
+        };
+        if let Err(err) = summit.sign_request(&delivery.actor, &mut request).await {
+            // Likely a local problem, such as the Db being unavailable.
+            return Attempt::Retry(err.into());
+        }
+        match summit.http_client().execute(request).await {
+            Ok(res) if res.status().is_success() => Attempt::Delivered,
+            Ok(res) => {
+                let status = res.status();
+                let err = anyhow!("remote responded with {status}");
+                match status {
+                    StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS => {
+                        Attempt::Retry(err)
+                    },
+                    status if status.is_client_error() => Attempt::Reject(err),
+                    _ => Attempt::Retry(err),
+                }
+            },
+            Err(err) => Attempt::Retry(err.into()),
+        }
+    }
+    /// Pending and in flight deliveries, followed by failed deliveries.
+    pub async fn list(&self, summit: &Summit) -> crate::Result<(Vec<Delivery>, Vec<Delivery>)> {
+        let mut pending = summit.db.deliveries(DeliveryState::InFlight).await?;
+        pending.extend(summit.db.deliveries(DeliveryState::Pending).await?);
+        let failed = summit.db.deliveries(DeliveryState::Failed).await?;
+        Ok((pending, failed))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        db::{Actor, ActorKind},
+        dev::db::{DevDb, DEV_COMMUNITY},
+        fedi::{signature::KeyAlgorithm, FediConfig},
+        uuid::RequestId,
+        SummitConfig,
+    };
+    use axum::{extract::State, routing::post, Router};
+    use http::HeaderMap;
+    use std::net::SocketAddr;
+
+    /// A stand-in remote inbox, responding with the given statuses in order and recording the
+    /// headers of each request.
+    async fn stand_in_inbox(statuses: Vec<StatusCode>) -> (SocketAddr, Arc<Mutex<Vec<HeaderMap>>>) {
+        type Hits = Arc<Mutex<Vec<HeaderMap>>>;
+        let hits = Hits::default();
+        let app = Router::new()
+            .route(
+                "/inbox",
+                post(
+                    |State((statuses, hits)): State<(Arc<Vec<StatusCode>>, Hits)>,
+                     headers: HeaderMap| async move {
+                        let mut hits = hits.lock().unwrap();
+                        hits.push(headers);
+                        statuses
+                            .get(hits.len() - 1)
+                            .copied()
+                            .unwrap_or(StatusCode::ACCEPTED)
+                    },
+                ),
+            )
+            .with_state((Arc::new(statuses), Arc::clone(&hits)));
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, hits)
+    }
+    async fn summit_with(delivery: DeliveryConfig) -> Arc<Summit> {
+        let config = SummitConfig {
+            fedi: FediConfig {
+                key_algorithm: KeyAlgorithm::Ed25519,
+                ..Default::default()
+            },
+            delivery,
+        };
+        Arc::new(Summit::new(config, Box::<DevDb>::default()))
+    }
+    fn actor() -> Actor {
+        Actor {
+            kind: ActorKind::Community,
+            name: DEV_COMMUNITY.into(),
+        }
+    }
+    async fn process(summit: &Arc<Summit>) {
+        for handle in summit.delivery_queue().process_due(summit).await.unwrap() {
+            handle.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_until_delivered() {
+        let (addr, hits) = stand_in_inbox(vec![
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::BAD_GATEWAY,
+        ])
+        .await;
+        let summit = summit_with(DeliveryConfig {
+            backoff_ms: 0,
+            ..Default::default()
+        })
+        .await;
+        let request_id = RequestId::new();
+        summit
+            .deliver(
+                &actor(),
+                &format!("http://{addr}/inbox"),
+                "{}".into(),
+                Some(request_id),
+            )
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            process(&summit).await;
+        }
+        {
+            let hits = hits.lock().unwrap();
+            assert_eq!(hits.len(), 3);
+            assert!(hits.iter().all(|headers| headers.contains_key("signature")));
+            assert!(hits.iter().all(|headers| {
+                headers.get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok())
+                    == Some(request_id.to_string().as_str())
+            }));
+        }
+        let (pending, failed) = summit.delivery_queue().list(&summit).await.unwrap();
+        assert!(pending.is_empty());
+        assert!(failed.is_empty());
+    }
+    #[tokio::test]
+    async fn fails_deliveries_to_dead_hosts() {
+        let (addr, hits) = stand_in_inbox(vec![StatusCode::SERVICE_UNAVAILABLE; 10]).await;
+        let summit = summit_with(DeliveryConfig {
+            backoff_ms: 0,
+            dead_host_after: 2,
+            ..Default::default()
+        })
+        .await;
+        summit
+            .deliver(&actor(), &format!("http://{addr}/inbox"), "{}".into(), None)
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            process(&summit).await;
+        }
+        assert_eq!(hits.lock().unwrap().len(), 2);
+        let (pending, failed) = summit.delivery_queue().list(&summit).await.unwrap();
+        assert!(pending.is_empty());
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].attempts, 3);
+    }
+    #[tokio::test]
+    async fn revives_dead_hosts_after_cooldown() {
+        let (addr, hits) = stand_in_inbox(vec![StatusCode::SERVICE_UNAVAILABLE]).await;
+        let summit = summit_with(DeliveryConfig {
+            backoff_ms: 0,
+            dead_host_after: 1,
+            dead_host_cooldown_ms: 0,
+            ..Default::default()
+        })
+        .await;
+        summit
+            .deliver(&actor(), &format!("http://{addr}/inbox"), "{}".into(), None)
+            .await
+            .unwrap();
+        process(&summit).await;
+        let host = summit.db.delivery_host(&addr.to_string()).await.unwrap();
+        assert!(host.unwrap().dead_since.is_some());
+        process(&summit).await;
+        assert_eq!(hits.lock().unwrap().len(), 2);
+        let host = summit.db.delivery_host(&addr.to_string()).await.unwrap();
+        assert_eq!(host.unwrap().dead_since, None);
+        let (pending, failed) = summit.delivery_queue().list(&summit).await.unwrap();
+        assert!(pending.is_empty() && failed.is_empty());
+    }
+    #[tokio::test]
+    async fn evicts_idle_host_limits() {
+        let (addr, _) = stand_in_inbox(vec![]).await;
+        let summit = summit_with(DeliveryConfig::default()).await;
+        summit
+            .deliver(&actor(), &format!("http://{addr}/inbox"), "{}".into(), None)
+            .await
+            .unwrap();
+        process(&summit).await;
+        let host_limits = || summit.delivery_queue().host_limits.lock().unwrap().len();
+        assert_eq!(host_limits(), 1);
+        process(&summit).await;
+        assert_eq!(host_limits(), 0);
+    }
+    #[tokio::test]
+    async fn rejected_deliveries_are_not_retried() {
+        let (addr, hits) = stand_in_inbox(vec![StatusCode::FORBIDDEN]).await;
+        let summit = summit_with(DeliveryConfig {
+            backoff_ms: 0,
+            ..Default::default()
+        })
+        .await;
+        summit
+            .deliver(&actor(), &format!("http://{addr}/inbox"), "{}".into(), None)
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            process(&summit).await;
+        }
+        assert_eq!(hits.lock().unwrap().len(), 1);
+        let (_, failed) = summit.delivery_queue().list(&summit).await.unwrap();
+        assert_eq!(failed.len(), 1);
+    }
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let config = DeliveryConfig {
+            backoff_ms: 1000,
+            max_backoff_ms: 5000,
+            ..Default::default()
+        };
+        assert_eq!(config.backoff(1), Duration::from_secs(1));
+        assert_eq!(config.backoff(2), Duration::from_secs(2));
+        assert_eq!(config.backoff(3), Duration::from_secs(4));
+        assert_eq!(config.backoff(4), Duration::from_secs(5));
+        assert_eq!(config.backoff(100), Duration::from_secs(5));
+    }
+}
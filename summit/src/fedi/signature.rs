@@ -0,0 +1,909 @@
+//! HTTP Signatures, as used by ActivityPub implementations to authenticate server to server
+//! requests.
+//!
+//! Implements the commonly deployed [cavage draft](https://datatracker.ietf.org/doc/html/draft-cavage-http-signatures-12),
+//! along with the `Digest` header for request bodies.
+use crate::{
+    uuid::RequestId,
+    web::{
+        extension::request_id::REQUEST_ID_HEADER, handler::webfinger::ACTIVITY_JSON_CONTENT_TYPE,
+    },
+};
+use axum::http::StatusCode;
+use data_encoding::BASE64;
+use ed25519_dalek::{
+    pkcs8::{
+        spki::der::pem::LineEnding, DecodePrivateKey, DecodePublicKey, EncodePrivateKey,
+        EncodePublicKey,
+    },
+    Signer as _, Verifier as _,
+};
+use http::{header, request::Parts, HeaderMap, HeaderValue, Method};
+use rand_core::OsRng;
+use rsa::{pkcs1v15, signature::SignatureEncoding, RsaPrivateKey, RsaPublicKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+use thiserror::Error;
+
+const RSA_KEY_BITS: usize = 2048;
+
+#[derive(Debug, Error)]
+pub enum SignatureError {
+    #[error("missing signature header")]
+    Missing,
+    #[error("malformed signature header: {0}")]
+    Malformed(&'static str),
+    #[error("unsupported signature algorithm: {0}")]
+    UnsupportedAlgorithm(String),
+    #[error("required header was not signed: {0}")]
+    Unsigned(&'static str),
+    #[error("signed header missing from request: {0}")]
+    MissingHeader(String),
+    #[error("request date outside of allowed clock skew")]
+    ClockSkew,
+    #[error("signature expired")]
+    Expired,
+    #[error("digest does not match body")]
+    DigestMismatch,
+    #[error("fetching key {key_id}: {source}")]
+    KeyFetch {
+        key_id: String,
+        source: anyhow::Error,
+    },
+    #[error("signature does not match key {0}")]
+    Invalid(String),
+    #[error("key {0} is not on a public http host")]
+    KeyHost(String),
+    #[error("key {key_id} is not on the host of actor {actor}")]
+    ActorMismatch { key_id: String, actor: String },
+    #[error("key {0} was already refreshed recently")]
+    RefreshLimited(String),
+    #[error(transparent)]
+    Key(anyhow::Error),
+}
+impl SignatureError {
+    /// The status code a rejected request should be responded to with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::KeyFetch { .. } => StatusCode::BAD_GATEWAY,
+            Self::Key(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeyAlgorithm {
+    /// RSA PKCS#1 v1.5 with SHA-256, the most widely supported across the fediverse.
+    #[default]
+    Rsa,
+    Ed25519,
+}
+
+/// A private key for a local actor, used to sign outgoing requests.
+#[derive(Clone)]
+pub enum PrivateKey {
+    Rsa(RsaPrivateKey),
+    Ed25519(ed25519_dalek::SigningKey),
+}
+impl PrivateKey {
+    /// Generate a new key. RSA key generation is expensive, so consider doing this on a blocking
+    /// thread.
+    pub fn generate(algorithm: KeyAlgorithm) -> Result<Self, SignatureError> {
+        match algorithm {
+            KeyAlgorithm::Rsa => RsaPrivateKey::new(&mut OsRng, RSA_KEY_BITS)
+                .map(Self::Rsa)
+                .map_err(|err| SignatureError::Key(err.into())),
+            KeyAlgorithm::Ed25519 => Ok(Self::Ed25519(ed25519_dalek::SigningKey::generate(
+                &mut OsRng,
+            ))),
+        }
+    }
+    pub fn algorithm(&self) -> KeyAlgorithm {
+        match self {
+            Self::Rsa(_) => KeyAlgorithm::Rsa,
+            Self::Ed25519(_) => KeyAlgorithm::Ed25519,
+        }
+    }
+    pub fn public_key(&self) -> PublicKey {
+        match self {
+            Self::Rsa(key) => PublicKey::Rsa(key.to_public_key()),
+            Self::Ed25519(key) => PublicKey::Ed25519(key.verifying_key()),
+        }
+    }
+    pub fn sign(&self, msg: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Rsa(key) => pkcs1v15::SigningKey::<Sha256>::new(key.clone())
+                .sign(msg)
+                .to_vec(),
+            Self::Ed25519(key) => key.sign(msg).to_vec(),
+        }
+    }
+    /// Encode the key as a PKCS#8 PEM, for storage.
+    pub fn to_pem(&self) -> Result<String, SignatureError> {
+        let pem = match self {
+            Self::Rsa(key) => key.to_pkcs8_pem(LineEnding::LF),
+            Self::Ed25519(key) => key.to_pkcs8_pem(LineEnding::LF),
+        }
+        .map_err(|err| SignatureError::Key(err.into()))?;
+        Ok(pem.to_string())
+    }
+    pub fn from_pem(pem: &str) -> Result<Self, SignatureError> {
+        if let Ok(key) = RsaPrivateKey::from_pkcs8_pem(pem) {
+            return Ok(Self::Rsa(key));
+        }
+        ed25519_dalek::SigningKey::from_pkcs8_pem(pem)
+            .map(Self::Ed25519)
+            .map_err(|err| SignatureError::Key(err.into()))
+    }
+}
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the key material itself.
+        f.debug_tuple("PrivateKey")
+            .field(&self.algorithm())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicKey {
+    Rsa(RsaPublicKey),
+    Ed25519(ed25519_dalek::VerifyingKey),
+}
+impl PublicKey {
+    pub fn algorithm(&self) -> KeyAlgorithm {
+        match self {
+            Self::Rsa(_) => KeyAlgorithm::Rsa,
+            Self::Ed25519(_) => KeyAlgorithm::Ed25519,
+        }
+    }
+    pub fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        match self {
+            Self::Rsa(key) => pkcs1v15::Signature::try_from(signature).map_or(false, |sig| {
+                pkcs1v15::VerifyingKey::<Sha256>::new(key.clone())
+                    .verify(msg, &sig)
+                    .is_ok()
+            }),
+            Self::Ed25519(key) => ed25519_dalek::Signature::from_slice(signature)
+                .map_or(false, |sig| key.verify(msg, &sig).is_ok()),
+        }
+    }
+    /// Encode the key as a SPKI PEM, the format expected in an actor's `publicKeyPem`.
+    pub fn to_pem(&self) -> Result<String, SignatureError> {
+        match self {
+            Self::Rsa(key) => key.to_public_key_pem(LineEnding::LF),
+            Self::Ed25519(key) => key.to_public_key_pem(LineEnding::LF),
+        }
+        .map_err(|err| SignatureError::Key(err.into()))
+    }
+    pub fn from_pem(pem: &str) -> Result<Self, SignatureError> {
+        if let Ok(key) = RsaPublicKey::from_public_key_pem(pem) {
+            return Ok(Self::Rsa(key));
+        }
+        ed25519_dalek::VerifyingKey::from_public_key_pem(pem)
+            .map(Self::Ed25519)
+            .map_err(|err| SignatureError::Key(err.into()))
+    }
+}
+
+/// The parsed parameters of a `Signature` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureParams {
+    pub key_id: String,
+    pub algorithm: Option<String>,
+    pub headers: Vec<String>,
+    pub signature: Vec<u8>,
+    pub created: Option<u64>,
+    pub expires: Option<u64>,
+}
+impl SignatureParams {
+    pub fn parse(s: &str) -> Result<Self, SignatureError> {
+        let mut params = HashMap::new();
+        let mut rest = s.trim();
+        while !rest.is_empty() {
+            let (key, after_key) = rest
+                .split_once('=')
+                .ok_or(SignatureError::Malformed("expected key=value"))?;
+            let (value, after_value) = if let Some(quoted) = after_key.strip_prefix('"') {
+                let end = quoted
+                    .find('"')
+                    .ok_or(SignatureError::Malformed("unterminated quote"))?;
+                (&quoted[..end], &quoted[end + 1..])
+            } else {
+                after_key.split_once(',').unwrap_or((after_key, ""))
+            };
+            params.insert(key.trim().to_ascii_lowercase(), value);
+            rest = after_value.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        }
+        let key_id = params
+            .remove("keyid")
+            .ok_or(SignatureError::Malformed("missing keyId"))?;
+        let signature = params
+            .remove("signature")
+            .ok_or(SignatureError::Malformed("missing signature"))?;
+        let signature = BASE64
+            .decode(signature.as_bytes())
+            .map_err(|_| SignatureError::Malformed("signature is not base64"))?;
+        let parse_ts = |v: &str| {
+            v.parse::<u64>()
+                .map_err(|_| SignatureError::Malformed("timestamp is not an integer"))
+        };
+        Ok(Self {
+            key_id: key_id.to_owned(),
+            algorithm: params.remove("algorithm").map(str::to_owned),
+            headers: params
+                .remove("headers")
+                // Per the spec, an absent headers param means only the date is signed.
+                .unwrap_or("date")
+                .split_whitespace()
+                .map(str::to_ascii_lowercase)
+                .collect(),
+            signature,
+            created: params.remove("created").map(parse_ts).transpose()?,
+            expires: params.remove("expires").map(parse_ts).transpose()?,
+        })
+    }
+    fn signing_string(
+        &self,
+        method: &Method,
+        path_and_query: &str,
+        headers: &HeaderMap,
+    ) -> Result<String, SignatureError> {
+        let mut lines = Vec::with_capacity(self.headers.len());
+        for name in &self.headers {
+            let value = match name.as_str() {
+                "(request-target)" => {
+                    format!("{} {path_and_query}", method.as_str().to_ascii_lowercase())
+                },
+                "(created)" => self
+                    .created
+                    .ok_or(SignatureError::Malformed("(created) signed but not set"))?
+                    .to_string(),
+                "(expires)" => self
+                    .expires
+                    .ok_or(SignatureError::Malformed("(expires) signed but not set"))?
+                    .to_string(),
+                name => {
+                    let values = headers
+                        .get_all(name)
+                        .iter()
+                        .map(|v| v.to_str().map(str::trim))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| SignatureError::Malformed("header value is not ascii"))?;
+                    if values.is_empty() {
+                        return Err(SignatureError::MissingHeader(name.to_owned()));
+                    }
+                    values.join(", ")
+                },
+            };
+            lines.push(format!("{name}: {value}"));
+        }
+        Ok(lines.join("\n"))
+    }
+    fn to_header_value(&self) -> Result<HeaderValue, SignatureError> {
+        let Self {
+            key_id,
+            algorithm,
+            headers,
+            signature,
+            ..
+        } = self;
+        let algorithm = algorithm.as_deref().unwrap_or("hs2019");
+        HeaderValue::try_from(format!(
+            r#"keyId="{key_id}",algorithm="{algorithm}",headers="{}",signature="{}""#,
+            headers.join(" "),
+            BASE64.encode(signature),
+        ))
+        .map_err(|err| SignatureError::Key(err.into()))
+    }
+}
+
+/// Compute the `Digest` header value for the given body.
+pub fn digest(body: &[u8]) -> String {
+    format!("SHA-256={}", BASE64.encode(&Sha256::digest(body)))
+}
+
+/// Sign the given request parts, adding `Host`, `Date`, `Digest` and `Signature` headers. `Date`
+/// and `Host` are left untouched if already set.
+pub fn sign(
+    method: &Method,
+    url: &reqwest::Url,
+    headers: &mut HeaderMap,
+    body: Option<&[u8]>,
+    key_id: &str,
+    key: &PrivateKey,
+) -> Result<(), SignatureError> {
+    let host = match url.port() {
+        Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+        None => url.host_str().unwrap_or_default().to_owned(),
+    };
+    let to_header_value =
+        |s: String| HeaderValue::try_from(s).map_err(|err| SignatureError::Key(err.into()));
+    if !headers.contains_key(header::HOST) {
+        headers.insert(header::HOST, to_header_value(host)?);
+    }
+    if !headers.contains_key(header::DATE) {
+        headers.insert(
+            header::DATE,
+            to_header_value(httpdate::fmt_http_date(SystemTime::now()))?,
+        );
+    }
+    let mut signed_headers = vec!["(request-target)", "host", "date"];
+    if let Some(body) = body {
+        headers.insert("digest", to_header_value(digest(body))?);
+        signed_headers.push("digest");
+    }
+    let path_and_query = match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_owned(),
+    };
+    let mut params = SignatureParams {
+        key_id: key_id.to_owned(),
+        algorithm: Some(
+            match key.algorithm() {
+                // Mastodon and friends only understand the legacy name for RSA.
+                KeyAlgorithm::Rsa => "rsa-sha256",
+                KeyAlgorithm::Ed25519 => "hs2019",
+            }
+            .to_owned(),
+        ),
+        headers: signed_headers.into_iter().map(str::to_owned).collect(),
+        signature: Vec::new(),
+        created: None,
+        expires: None,
+    };
+    let signing_string = params.signing_string(method, &path_and_query, headers)?;
+    params.signature = key.sign(signing_string.as_bytes());
+    headers.insert("signature", params.to_header_value()?);
+    Ok(())
+}
+
+/// Sign an outgoing request, such as a federated delivery.
+pub fn sign_request(
+    request: &mut reqwest::Request,
+    key_id: &str,
+    key: &PrivateKey,
+) -> Result<(), SignatureError> {
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .map(<[u8]>::to_vec);
+    let method = request.method().clone();
+    let url = request.url().clone();
+    sign(
+        &method,
+        &url,
+        request.headers_mut(),
+        body.as_deref(),
+        key_id,
+        key,
+    )
+}
+
+/// Verify the signature of an incoming request, returning the verified signature parameters.
+pub async fn verify_request(
+    parts: &Parts,
+    body: &[u8],
+    max_clock_skew: Duration,
+    keys: &RemoteKeyCache,
+) -> Result<SignatureParams, SignatureError> {
+    let params = parse_request(parts)?;
+    check_request(&params, parts, body, SystemTime::now(), max_clock_skew)?;
+    let path_and_query = parts
+        .uri
+        .path_and_query()
+        .map_or("/", |path_and_query| path_and_query.as_str());
+    let signing_string = params.signing_string(&parts.method, path_and_query, &parts.headers)?;
+    // Before anything is fetched from the key id, which is chosen by the sender.
+    check_key_id(&params.key_id, body)?;
+    let request_id = parts.extensions.get::<RequestId>().copied();
+    let key = keys.get(&params.key_id, request_id).await?;
+    if verify_with(&params, &key, &signing_string)? {
+        return Ok(params);
+    }
+    // The remote may have rotated their key since we cached it, try once more with a fresh copy.
+    let key = match keys.refresh(&params.key_id, request_id).await {
+        Err(SignatureError::RefreshLimited(key_id)) => return Err(SignatureError::Invalid(key_id)),
+        res => res?,
+    };
+    if verify_with(&params, &key, &signing_string)? {
+        Ok(params)
+    } else {
+        Err(SignatureError::Invalid(params.key_id))
+    }
+}
+fn parse_request(parts: &Parts) -> Result<SignatureParams, SignatureError> {
+    if let Some(value) = parts.headers.get("signature") {
+        let value = value
+            .to_str()
+            .map_err(|_| SignatureError::Malformed("header value is not ascii"))?;
+        return SignatureParams::parse(value);
+    }
+    let value = parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Signature "))
+        .ok_or(SignatureError::Missing)?;
+    SignatureParams::parse(value)
+}
+/// Check the key id can be fetched, being on a public host, and that it's on the same host as the
+/// actor of the activity in the body, if any. So that senders can neither have us fetch from
+/// internal hosts, nor sign activities as actors of other hosts.
+fn check_key_id(key_id: &str, body: &[u8]) -> Result<(), SignatureError> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ActorRef {
+        Id(String),
+        Object { id: String },
+    }
+    #[derive(Deserialize)]
+    struct Activity {
+        actor: ActorRef,
+    }
+    let key_host = || SignatureError::KeyHost(key_id.to_owned());
+    let key_url = reqwest::Url::parse(key_id).map_err(|_| key_host())?;
+    if !matches!(key_url.scheme(), "https" | "http") || !is_public_host(&key_url) {
+        return Err(key_host());
+    }
+    if body.is_empty() {
+        return Ok(());
+    }
+    let Activity { actor } = serde_json::from_slice(body)
+        .map_err(|_| SignatureError::Malformed("body is not an activity with an actor"))?;
+    let (ActorRef::Id(actor) | ActorRef::Object { id: actor }) = actor;
+    let same_host = reqwest::Url::parse(&actor).map_or(false, |actor_url| {
+        actor_url.host() == key_url.host()
+            && actor_url.port_or_known_default() == key_url.port_or_known_default()
+    });
+    if !same_host {
+        return Err(SignatureError::ActorMismatch {
+            key_id: key_id.to_owned(),
+            actor,
+        });
+    }
+    Ok(())
+}
+/// Whether the host of the url isn't local, such as loopback, private or link-local addresses.
+///
+/// Only literal addresses can be checked, names resolving to internal addresses aren't caught.
+fn is_public_host(url: &reqwest::Url) -> bool {
+    fn is_public_v4(ip: Ipv4Addr) -> bool {
+        let [a, b, ..] = ip.octets();
+        // 100.64.0.0/10 is shared, carrier-grade NAT space.
+        let shared = a == 100 && (b & 0xc0) == 64;
+        !(ip.is_loopback()
+            || ip.is_private()
+            || ip.is_link_local()
+            || ip.is_unspecified()
+            || ip.is_broadcast()
+            || shared)
+    }
+    fn is_public_v6(ip: Ipv6Addr) -> bool {
+        if let Some(ip) = ip.to_ipv4_mapped() {
+            return is_public_v4(ip);
+        }
+        let first = ip.segments()[0];
+        let unique_local = (first & 0xfe00) == 0xfc00;
+        let link_local = (first & 0xffc0) == 0xfe80;
+        !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+    }
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    // IPv6 hosts are bracketed.
+    match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(IpAddr::V4(ip)) => is_public_v4(ip),
+        Ok(IpAddr::V6(ip)) => is_public_v6(ip),
+        Err(_) => {
+            let domain = host.trim_end_matches('.').to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        },
+    }
+}
+/// Check everything about the request that doesn't require the key.
+fn check_request(
+    params: &SignatureParams,
+    parts: &Parts,
+    body: &[u8],
+    now: SystemTime,
+    max_clock_skew: Duration,
+) -> Result<(), SignatureError> {
+    let signed = |name: &str| params.headers.iter().any(|h| h == name);
+    if !signed("(request-target)") {
+        return Err(SignatureError::Unsigned("(request-target)"));
+    }
+    if !signed("host") {
+        return Err(SignatureError::Unsigned("host"));
+    }
+    let now_secs = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    if let Some(expires) = params.expires {
+        if expires < now_secs {
+            return Err(SignatureError::Expired);
+        }
+    }
+    let signed_at = if signed("date") {
+        let date = parts
+            .headers
+            .get(header::DATE)
+            .and_then(|date| date.to_str().ok())
+            .ok_or_else(|| SignatureError::MissingHeader("date".to_owned()))?;
+        httpdate::parse_http_date(date).map_err(|_| SignatureError::Malformed("invalid date"))?
+    } else if signed("(created)") {
+        let created = params
+            .created
+            .ok_or(SignatureError::Malformed("(created) signed but not set"))?;
+        SystemTime::UNIX_EPOCH + Duration::from_secs(created)
+    } else {
+        return Err(SignatureError::Unsigned("date"));
+    };
+    let skew = now
+        .duration_since(signed_at)
+        .or_else(|_| signed_at.duration_since(now))
+        .unwrap_or_default();
+    if skew > max_clock_skew {
+        return Err(SignatureError::ClockSkew);
+    }
+    if !body.is_empty() || parts.method == Method::POST {
+        if !signed("digest") {
+            return Err(SignatureError::Unsigned("digest"));
+        }
+        let expected = digest(body);
+        let matches = parts
+            .headers
+            .get_all("digest")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|digest| {
+                digest
+                    .get(..8)
+                    .map_or(false, |alg| alg.eq_ignore_ascii_case("sha-256="))
+            })
+            .any(|digest| digest[8..] == expected[8..]);
+        if !matches {
+            return Err(SignatureError::DigestMismatch);
+        }
+    }
+    Ok(())
+}
+fn verify_with(
+    params: &SignatureParams,
+    key: &PublicKey,
+    signing_string: &str,
+) -> Result<bool, SignatureError> {
+    match (params.algorithm.as_deref(), key.algorithm()) {
+        // hs2019 defers the algorithm to the key itself.
+        (None | Some("hs2019"), _)
+        | (Some("rsa-sha256"), KeyAlgorithm::Rsa)
+        | (Some("ed25519"), KeyAlgorithm::Ed25519) => {},
+        (Some(algorithm), _) => {
+            return Err(SignatureError::UnsupportedAlgorithm(algorithm.to_owned()))
+        },
+    }
+    Ok(key.verify(signing_string.as_bytes(), &params.signature))
+}
+
+/// At most this many keys are cached, evicting the oldest beyond it, as senders choose key ids.
+const MAX_CACHED_KEYS: usize = 10_000;
+/// Key documents are small, an actor with its key is a few KiB at most.
+const MAX_KEY_DOC_BYTES: usize = 64 * 1024;
+const KEY_FETCH_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Inbox requests wait on the fetch of their key, so this bounds how long a slow key server
+/// can hold them.
+const KEY_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A cache of remote actor public keys, keyed by their `keyId`.
+#[derive(Debug)]
+pub struct RemoteKeyCache {
+    client: reqwest::Client,
+    ttl: Duration,
+    keys: Mutex<HashMap<String, (PublicKey, Instant)>>,
+    /// When each key was last refreshed, as they're refreshed at most once per TTL. Otherwise
+    /// every request with a bad signature would have us fetch the key again.
+    refreshed: Mutex<HashMap<String, Instant>>,
+}
+impl RemoteKeyCache {
+    /// Keys are fetched with their own client, which doesn't follow redirects, as only the key
+    /// id's host is checked to be public.
+    pub fn new(ttl: Duration) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(crate::USER_AGENT)
+            .redirect(reqwest::redirect::Policy::none())
+            .connect_timeout(KEY_FETCH_CONNECT_TIMEOUT)
+            .timeout(KEY_FETCH_TIMEOUT)
+            .build()
+            .expect("http client to build with static configuration");
+        Self {
+            client,
+            ttl,
+            keys: Default::default(),
+            refreshed: Default::default(),
+        }
+    }
+    /// Insert a key directly, bypassing fetching.
+    pub fn insert(&self, key_id: impl Into<String>, key: PublicKey) {
+        if let Ok(mut keys) = self.keys.lock() {
+            if keys.len() >= MAX_CACHED_KEYS {
+                keys.retain(|_, (_, fetched_at)| fetched_at.elapsed() < self.ttl);
+            }
+            if keys.len() >= MAX_CACHED_KEYS {
+                let oldest = keys
+                    .iter()
+                    .min_by_key(|(_, (_, fetched_at))| *fetched_at)
+                    .map(|(key_id, _)| key_id.clone());
+                if let Some(oldest) = oldest {
+                    keys.remove(&oldest);
+                }
+            }
+            keys.insert(key_id.into(), (key, Instant::now()));
+        }
+    }
+    /// Return the cached key, fetching it if absent or expired.
+    ///
+    /// Any fetch carries the id of the request the key is needed for.
+    pub async fn get(
+        &self,
+        key_id: &str,
+        request_id: Option<RequestId>,
+    ) -> Result<PublicKey, SignatureError> {
+        let cached = self.keys.lock().ok().and_then(|keys| {
+            keys.get(key_id)
+                .filter(|(_, fetched_at)| fetched_at.elapsed() < self.ttl)
+                .map(|(key, _)| key.clone())
+        });
+        match cached {
+            Some(key) => Ok(key),
+            None => self.fetch_and_insert(key_id, request_id).await,
+        }
+    }
+    /// Fetch the key, replacing any cached copy, unless it was already refreshed within the TTL.
+    pub async fn refresh(
+        &self,
+        key_id: &str,
+        request_id: Option<RequestId>,
+    ) -> Result<PublicKey, SignatureError> {
+        {
+            let mut refreshed = self
+                .refreshed
+                .lock()
+                .map_err(|err| SignatureError::Key(anyhow::anyhow!("{err}")))?;
+            refreshed.retain(|_, refreshed_at| refreshed_at.elapsed() < self.ttl);
+            if refreshed.contains_key(key_id) || refreshed.len() >= MAX_CACHED_KEYS {
+                return Err(SignatureError::RefreshLimited(key_id.to_owned()));
+            }
+            refreshed.insert(key_id.to_owned(), Instant::now());
+        }
+        self.fetch_and_insert(key_id, request_id).await
+    }
+    async fn fetch_and_insert(
+        &self,
+        key_id: &str,
+        request_id: Option<RequestId>,
+    ) -> Result<PublicKey, SignatureError> {
+        let key =
+            self.fetch(key_id, request_id)
+                .await
+                .map_err(|source| SignatureError::KeyFetch {
+                    key_id: key_id.to_owned(),
+                    source,
+                })?;
+        self.insert(key_id, key.clone());
+        Ok(key)
+    }
+    async fn fetch(
+        &self,
+        key_id: &str,
+        request_id: Option<RequestId>,
+    ) -> anyhow::Result<PublicKey> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct KeyDoc {
+            public_key_pem: String,
+        }
+        /// The key id typically points at the actor, with the key embedded. Some implementations
+        /// serve the key document directly though.
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Doc {
+            #[serde(rename_all = "camelCase")]
+            Actor {
+                public_key: KeyDoc,
+            },
+            Key(KeyDoc),
+        }
+        let mut url = reqwest::Url::parse(key_id)?;
+        url.set_fragment(None);
+        let mut request = self
+            .client
+            .get(url)
+            .header(header::ACCEPT, ACTIVITY_JSON_CONTENT_TYPE);
+        if let Some(request_id) = request_id {
+            request = request.header(REQUEST_ID_HEADER, request_id.to_string());
+        }
+        let mut response = request.send().await?;
+        if !response.status().is_success() {
+            anyhow::bail!("key server responded {}", response.status());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > MAX_KEY_DOC_BYTES {
+                anyhow::bail!("key document exceeds {MAX_KEY_DOC_BYTES} bytes");
+            }
+            body.extend_from_slice(&chunk);
+        }
+        let doc = serde_json::from_slice::<Doc>(&body)?;
+        let (Doc::Actor {
+            public_key: KeyDoc { public_key_pem },
+        }
+        | Doc::Key(KeyDoc { public_key_pem })) = doc;
+        Ok(PublicKey::from_pem(&public_key_pem)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY_ID: &str = "https://remote.example/u/bob#main-key";
+
+    fn signed_parts(key: &PrivateKey, body: &[u8], date: Option<SystemTime>) -> Parts {
+        let url = reqwest::Url::parse("https://summit.example/u/alice/inbox").unwrap();
+        let mut headers = HeaderMap::new();
+        if let Some(date) = date {
+            headers.insert(
+                header::DATE,
+                HeaderValue::try_from(httpdate::fmt_http_date(date)).unwrap(),
+            );
+        }
+        sign(&Method::POST, &url, &mut headers, Some(body), KEY_ID, key).unwrap();
+        let mut req = http::Request::post("/u/alice/inbox").body(()).unwrap();
+        *req.headers_mut() = headers;
+        req.into_parts().0
+    }
+    fn cache_with(key: &PrivateKey) -> RemoteKeyCache {
+        let cache = RemoteKeyCache::new(Duration::from_secs(60));
+        cache.insert(KEY_ID, key.public_key());
+        cache
+    }
+
+    #[tokio::test]
+    async fn sign_verify_roundtrip() {
+        for algorithm in [KeyAlgorithm::Ed25519, KeyAlgorithm::Rsa] {
+            let key = PrivateKey::generate(algorithm).unwrap();
+            let body = br#"{"type":"Follow","actor":"https://remote.example/u/bob"}"#;
+            let parts = signed_parts(&key, body, None);
+            let params = verify_request(&parts, body, Duration::from_secs(30), &cache_with(&key))
+                .await
+                .unwrap();
+            assert_eq!(params.key_id, KEY_ID);
+            let pem = key.public_key().to_pem().unwrap();
+            assert_eq!(PublicKey::from_pem(&pem).unwrap(), key.public_key());
+        }
+    }
+    #[tokio::test]
+    async fn rejects_tampered_body() {
+        let key = PrivateKey::generate(KeyAlgorithm::Ed25519).unwrap();
+        let parts = signed_parts(&key, b"original", None);
+        let res = verify_request(
+            &parts,
+            b"tampered",
+            Duration::from_secs(30),
+            &cache_with(&key),
+        )
+        .await;
+        assert!(matches!(res, Err(SignatureError::DigestMismatch)));
+    }
+    #[tokio::test]
+    async fn rejects_clock_skew() {
+        let key = PrivateKey::generate(KeyAlgorithm::Ed25519).unwrap();
+        let date = SystemTime::now() - Duration::from_secs(600);
+        let parts = signed_parts(&key, b"body", Some(date));
+        let res = verify_request(&parts, b"body", Duration::from_secs(30), &cache_with(&key)).await;
+        assert!(matches!(res, Err(SignatureError::ClockSkew)));
+    }
+    #[tokio::test]
+    async fn rejects_foreign_and_internal_key_ids() {
+        let key = PrivateKey::generate(KeyAlgorithm::Ed25519).unwrap();
+        let body = br#"{"type":"Follow","actor":"https://other.example/u/bob"}"#;
+        let parts = signed_parts(&key, body, None);
+        let res = verify_request(&parts, body, Duration::from_secs(30), &cache_with(&key)).await;
+        assert!(matches!(res, Err(SignatureError::ActorMismatch { .. })));
+        for key_id in [
+            "http://localhost/u/bob#main-key",
+            "http://127.0.0.1/u/bob#main-key",
+            "http://10.0.0.2/u/bob#main-key",
+            "http://169.254.169.254/latest#main-key",
+            "http://[::1]/u/bob#main-key",
+            "http://[fd00::1]/u/bob#main-key",
+            "file:///etc/passwd",
+        ] {
+            let res = check_key_id(key_id, b"");
+            assert!(matches!(res, Err(SignatureError::KeyHost(_))), "{key_id}");
+        }
+        assert!(check_key_id(KEY_ID, b"").is_ok());
+    }
+    #[tokio::test]
+    async fn refreshes_at_most_once_per_ttl() {
+        let cache = RemoteKeyCache::new(Duration::from_secs(60));
+        // Nothing listens, so the first refresh fails, but still counts.
+        let key_id = "http://127.0.0.1:9/u/bob#main-key";
+        let res = cache.refresh(key_id, None).await;
+        assert!(matches!(res, Err(SignatureError::KeyFetch { .. })));
+        let res = cache.refresh(key_id, None).await;
+        assert!(matches!(res, Err(SignatureError::RefreshLimited(_))));
+    }
+    /// Serve the key of `key` from `/key`, and redirect `/redirect` to it.
+    async fn key_server(key: &PrivateKey) -> std::net::SocketAddr {
+        use axum::{response::Redirect, routing::get, Json, Router};
+        let pem = key.public_key().to_pem().unwrap();
+        let app = Router::new()
+            .route(
+                "/key",
+                get(|| async move { Json(serde_json::json!({ "publicKeyPem": pem })) }),
+            )
+            .route("/redirect", get(|| async { Redirect::to("/key") }))
+            .route("/huge", get(|| async { vec![b' '; MAX_KEY_DOC_BYTES + 1] }));
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+    #[tokio::test]
+    async fn does_not_follow_redirects() {
+        let key = PrivateKey::generate(KeyAlgorithm::Ed25519).unwrap();
+        let addr = key_server(&key).await;
+        let cache = RemoteKeyCache::new(Duration::from_secs(60));
+        let fetched = cache
+            .fetch(&format!("http://{addr}/key#main-key"), None)
+            .await
+            .unwrap();
+        assert_eq!(fetched, key.public_key());
+        let res = cache
+            .fetch(&format!("http://{addr}/redirect#main-key"), None)
+            .await;
+        assert!(res.is_err());
+        let res = cache
+            .fetch(&format!("http://{addr}/huge#main-key"), None)
+            .await;
+        assert!(res.is_err());
+    }
+    #[test]
+    fn evicts_the_oldest_key_when_full() {
+        let key = PrivateKey::generate(KeyAlgorithm::Ed25519).unwrap();
+        let cache = RemoteKeyCache::new(Duration::from_secs(60));
+        for i in 0..=MAX_CACHED_KEYS {
+            cache.insert(
+                format!("https://remote.example/u/{i}#main-key"),
+                key.public_key(),
+            );
+        }
+        let keys = cache.keys.lock().unwrap();
+        assert_eq!(keys.len(), MAX_CACHED_KEYS);
+        assert!(!keys.contains_key("https://remote.example/u/0#main-key"));
+    }
+    #[test]
+    fn parse_params() {
+        let params = SignatureParams::parse(
+            r#"keyId="https://a.example/u/b#main-key",algorithm="rsa-sha256",headers="(request-target) host date",signature="YWJj""#,
+        )
+        .unwrap();
+        assert_eq!(params.key_id, "https://a.example/u/b#main-key");
+        assert_eq!(params.algorithm.as_deref(), Some("rsa-sha256"));
+        assert_eq!(params.headers, ["(request-target)", "host", "date"]);
+        assert_eq!(params.signature, b"abc");
+    }
+}
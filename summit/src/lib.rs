@@ -1,26 +1,52 @@
-use crate::db::{CreatePost, Db, DbError, Post};
+use crate::{
+    content::ContentRefs,
+    db::{
+        Actor, Community, CreatePost, Db, DbError, Delivery, DeliveryHost, DeliveryState, FediAddr,
+        LocalUser, NewDelivery, NewNotification, Notification, NotificationKind, Post, Preferences,
+        Profile, Stats,
+    },
+    fedi::{
+        delivery::{DeliveryConfig, DeliveryQueue},
+        signature::{self, PrivateKey, RemoteKeyCache, SignatureError, SignatureParams},
+        FediConfig,
+    },
+    metrics::{db::MeasuredDb, Metrics},
+    web::shutdown::ShutdownSignal,
+};
 use anyhow::anyhow;
-use date_time::TimeZone;
+use clap::Parser;
+use compact_str::format_compact;
+use data_encoding::BASE64URL_NOPAD;
 use kanal::{AsyncReceiver, AsyncSender};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
 use std::{
     collections::{BTreeMap, HashMap},
     fmt,
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 use thiserror::Error;
-use tracing::{debug, error, instrument};
-use uuid::{RequestId, UserId};
+use tracing::{debug, error, info, instrument};
+use uuid::{NotificationId, PostId, RequestId, UserId};
 
+pub mod content;
 pub mod date_time;
 pub mod db;
 #[cfg(any(test, feature = "dev"))]
 pub mod dev;
+pub mod fedi;
+pub mod metrics;
 pub mod uuid;
 pub mod web;
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error(transparent)]
+    Signature(#[from] SignatureError),
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -32,20 +58,58 @@ impl From<DbError> for Error {
     }
 }
 
+/// The user agent of requests to other servers.
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Parser, Debug, Default, Clone)]
+pub struct SummitConfig {
+    #[command(flatten)]
+    pub fedi: FediConfig,
+    #[command(flatten)]
+    pub delivery: DeliveryConfig,
+}
+
 pub struct Summit {
+    config: SummitConfig,
     db: Box<dyn Db>,
+    http_client: reqwest::Client,
+    remote_keys: RemoteKeyCache,
+    delivery_queue: DeliveryQueue,
+    metrics: Arc<Metrics>,
+    /// The key of CSRF tokens, new on each start so that forms rendered before then are refused.
+    csrf_key: [u8; 32],
+    // TODO: Change to a local bounded queue, configurable size, with the ability to offload load
+    // to disk.
     content_process_queue: (AsyncSender<Post>, AsyncReceiver<Post>),
+    /// Whether [`Self::process_content`] is running.
+    processing_content: AtomicBool,
+    /// Open event streams, per user and the request which opened them.
+    ///
     /// CONCURRENCY: Prototype design, this is naive on purpose. I also have no firm design on
-    /// propagating and filtering events to users yet, so don't prematurely engineer .. right?
-    user_events: Mutex<HashMap<UserId, BTreeMap<RequestId, (AsyncSender<()>, AsyncReceiver<()>)>>>,
+    /// filtering events to users yet, so don't prematurely engineer .. right?
+    user_events: Mutex<HashMap<UserId, BTreeMap<RequestId, AsyncSender<UserEvent>>>>,
 }
 impl Summit {
-    pub fn new(db: Box<dyn Db>) -> Self {
+    pub fn new(config: SummitConfig, db: Box<dyn Db>) -> Self {
+        let http_client = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .expect("http client to build with static configuration");
+        let remote_keys = RemoteKeyCache::new(config.fedi.remote_key_ttl());
+        let delivery_queue = DeliveryQueue::new(config.delivery.clone());
+        let metrics = Arc::<Metrics>::default();
+        let mut csrf_key = [0u8; 32];
+        OsRng.fill_bytes(&mut csrf_key);
         Self {
-            db,
-            // TODO: Change to a local bounded queue, configurable size, with the ability to offload
-            // load to disk.
+            config,
+            db: Box::new(MeasuredDb::new(db, Arc::clone(&metrics))),
+            http_client,
+            remote_keys,
+            delivery_queue,
+            metrics,
+            csrf_key,
             content_process_queue: kanal::unbounded_async(),
+            processing_content: AtomicBool::new(false),
             user_events: Default::default(),
         }
     }
@@ -56,56 +120,475 @@ impl Summit {
     ))]
     pub async fn create_post(&self, create_post: CreatePost) -> Result<Post> {
         debug!("creating post");
-        let post = self.db.create_post(create_post).await?;
+        let refs = ContentRefs::scan([create_post.title.as_str(), create_post.body.as_str()]);
+        let post = self.db.create_post(create_post, refs).await?;
+        self.metrics.posts_created.inc();
+        self.notify_mentions(&post).await;
         self.send_post_event(post.clone()).await;
         Ok(post)
     }
+    /// Notify any local users mentioned by the given post.
+    async fn notify_mentions(&self, post: &Post) {
+        let local_host = self.config.fedi.host();
+        for addr in &post.refs.mentions {
+            if !addr.host.eq_ignore_ascii_case(local_host) {
+                // TODO: Deliver mentions of remote users, once posts federate.
+                continue;
+            }
+            let res: Result<()> = async {
+                let Some(user) = self.db.local_user(&addr.user).await? else {
+                    return Ok(());
+                };
+                self.notify(NewNotification {
+                    recipient: user.id,
+                    kind: NotificationKind::Mention {
+                        post: post.id,
+                        by: post.author.fedi_addr.clone(),
+                    },
+                })
+                .await?;
+                Ok(())
+            }
+            .await;
+            if let Err(err) = res {
+                error!(?err, %addr, "failed to notify mentioned user");
+            }
+        }
+    }
     #[instrument(skip_all, fields(
         content_process_queue_len=self.content_process_queue.0.len()),
     )]
     async fn send_post_event(&self, post: Post) {
-        // TODO: per-user rules. But that requires users, which don't exist yet.
         if let Err(err) = self.content_process_queue.0.send(post).await {
             error!(?err, "failed to push post to content process queue");
         }
     }
-    /// Return a channel receiver for events matching the given [`UserId`].
-    //
-    // TODO: Centralize channel types, don't expose underlying impl - currently Kanal.
-    pub fn user_events(&self, _user_id: UserId) -> AsyncReceiver<Post> {
-        self.content_process_queue.1.clone()
+    /// Process queued posts until shutdown, fanning each out to the open event streams of users.
+    pub async fn process_content(&self, shutdown_signal: ShutdownSignal) {
+        /// Clears the running flag however processing stops, including by panicking.
+        struct Running<'a>(&'a AtomicBool);
+        impl Drop for Running<'_> {
+            fn drop(&mut self) {
+                self.0.store(false, Ordering::Relaxed);
+            }
+        }
+        self.processing_content.store(true, Ordering::Relaxed);
+        let _running = Running(&self.processing_content);
+        loop {
+            let post = tokio::select! {
+                res = self.content_process_queue.1.recv() => match res {
+                    Ok(post) => post,
+                    Err(err) => {
+                        error!(?err, "content process queue closed");
+                        return;
+                    },
+                },
+                () = shutdown_signal.recv() => break,
+            };
+            self.process_post(post);
+        }
+        info!(
+            queued = self.content_process_queue.1.len(),
+            "content processing stopped"
+        );
+    }
+    /// Whether content is being processed, so that posts reach live streams.
+    pub fn is_processing_content(&self) -> bool {
+        self.processing_content.load(Ordering::Relaxed)
+    }
+    /// Process the posts queued so far, returning how many there were, such as to drain the
+    /// queue on shutdown once nothing queues more.
+    pub async fn process_queued_content(&self) -> usize {
+        let mut processed = 0;
+        while let Ok(Some(post)) = self.content_process_queue.1.try_recv() {
+            self.process_post(post);
+            processed += 1;
+            // Yield between posts, so that draining can be given a deadline.
+            tokio::task::yield_now().await;
+        }
+        processed
+    }
+    fn process_post(&self, post: Post) {
+        // TODO: per-user rules, eg only posts of subscribed communities.
+        self.send_user_events(None, UserEvent::Post(post));
+    }
+    /// Create a notification, and update the unread count of the recipient's open event streams.
+    #[instrument(skip_all, fields(recipient = %new_notification.recipient))]
+    pub async fn notify(&self, new_notification: NewNotification) -> Result<Notification> {
+        let notification = self.db.create_notification(new_notification).await?;
+        debug!(id = %notification.id, "created notification");
+        self.send_unread_notifications(notification.recipient)
+            .await?;
+        Ok(notification)
+    }
+    pub async fn notifications(
+        &self,
+        user_id: UserId,
+        before: Option<NotificationId>,
+        limit: usize,
+    ) -> Result<Vec<Notification>> {
+        let notifications = self.db.notifications(user_id, before, limit).await?;
+        Ok(notifications)
+    }
+    pub async fn unread_notifications(&self, user_id: UserId) -> Result<u64> {
+        let unread = self.db.unread_notifications(user_id).await?;
+        Ok(unread)
+    }
+    /// Mark the given notifications as read, or all of them if `None`.
+    pub async fn mark_notifications_read(
+        &self,
+        user_id: UserId,
+        ids: Option<&[NotificationId]>,
+    ) -> Result<()> {
+        self.db.mark_notifications_read(user_id, ids).await?;
+        self.send_unread_notifications(user_id).await
+    }
+    async fn send_unread_notifications(&self, user_id: UserId) -> Result<()> {
+        let unread = self.db.unread_notifications(user_id).await?;
+        self.send_user_events(Some(user_id), UserEvent::Notifications { unread });
+        Ok(())
+    }
+    /// Send an event to the open event streams of the given user, or every user if `None`.
+    ///
+    /// Streams which are full are skipped rather than waited on, as a slow client shouldn't hold
+    /// up everyone else.
+    fn send_user_events(&self, user_id: Option<UserId>, event: UserEvent) {
+        let user_events = match self.user_events.lock() {
+            Ok(user_events) => user_events,
+            Err(err) => {
+                error!(%err, "failed to lock user events");
+                return;
+            },
+        };
+        let streams: Box<dyn Iterator<Item = _>> = match user_id {
+            Some(user_id) => Box::new(user_events.get(&user_id).into_iter().flatten()),
+            None => Box::new(user_events.values().flatten()),
+        };
+        for (req_id, sender) in streams {
+            match sender.try_send(event.clone()) {
+                Ok(true) => {},
+                Ok(false) => debug!(%req_id, "event stream full, dropping event"),
+                Err(err) => debug!(%req_id, ?err, "event stream closed, dropping event"),
+            }
+        }
+    }
+    pub fn fedi_config(&self) -> &FediConfig {
+        &self.config.fedi
+    }
+    pub async fn posts(&self, before: Option<PostId>, limit: usize) -> Result<Vec<Post>> {
+        let posts = self.db.posts(before, limit).await?;
+        Ok(posts)
+    }
+    pub async fn post(&self, id: PostId) -> Result<Option<Post>> {
+        let post = self.db.post(id).await?;
+        Ok(post)
+    }
+    pub async fn tag_posts(&self, tag: &str, limit: usize) -> Result<Vec<Post>> {
+        let posts = self.db.tag_posts(tag, limit).await?;
+        Ok(posts)
+    }
+    pub async fn local_actor(&self, name: &str) -> Result<Option<Actor>> {
+        let actor = self.db.local_actor(name).await?;
+        Ok(actor)
+    }
+    pub async fn local_user(&self, name: &str) -> Result<Option<LocalUser>> {
+        let user = self.db.local_user(name).await?;
+        Ok(user)
+    }
+    pub async fn community(&self, name: &str) -> Result<Option<Community>> {
+        let community = self.db.community(name).await?;
+        Ok(community)
+    }
+    pub async fn preferences(&self, user_id: UserId) -> Result<Preferences> {
+        let preferences = self.db.preferences(user_id).await?;
+        Ok(preferences)
+    }
+    pub async fn set_preferences(&self, user_id: UserId, preferences: Preferences) -> Result<()> {
+        self.db.set_preferences(user_id, preferences).await?;
+        Ok(())
     }
-    pub async fn posts(&self) -> Result<Vec<Post>> {
-        let posts = self.db.posts().await?;
+    pub async fn profile(&self, addr: &FediAddr) -> Result<Option<Profile>> {
+        let profile = self.db.profile(addr).await?;
+        Ok(profile)
+    }
+    pub async fn user_posts(
+        &self,
+        author: &FediAddr,
+        before: Option<PostId>,
+        limit: usize,
+    ) -> Result<Vec<Post>> {
+        let posts = self.db.user_posts(author, before, limit).await?;
         Ok(posts)
     }
-    pub async fn open_event_stream(
+    /// Create an API token for the given user, returning the token. Only its hash is stored, so
+    /// it can't be shown again.
+    pub async fn create_api_token(&self, user_id: UserId) -> Result<String> {
+        let mut token = [0u8; 32];
+        OsRng.fill_bytes(&mut token);
+        let token = BASE64URL_NOPAD.encode(&token);
+        self.db
+            .insert_api_token(user_id, Sha256::digest(&token).to_vec())
+            .await?;
+        Ok(token)
+    }
+    /// The user the given API token belongs to, if valid.
+    pub async fn api_token_user(&self, token: &str) -> Result<Option<LocalUser>> {
+        let user = self.db.api_token_user(&Sha256::digest(token)).await?;
+        Ok(user)
+    }
+    /// A token for the forms of the given user, proving they were rendered here rather than
+    /// submitted from another site.
+    pub fn csrf_token(&self, user_id: UserId) -> String {
+        let token = Sha256::new()
+            .chain_update(self.csrf_key)
+            .chain_update(user_id.as_bytes())
+            .finalize();
+        BASE64URL_NOPAD.encode(&token)
+    }
+    /// Whether the given token is the CSRF token of the given user.
+    pub fn verify_csrf_token(&self, user_id: UserId, token: &str) -> bool {
+        let expected = self.csrf_token(user_id);
+        // In constant time, so that the expected token can't be guessed a byte at a time.
+        expected.len() == token.len()
+            && expected
+                .bytes()
+                .zip(token.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+    /// Check the Db is reachable and migrated.
+    pub async fn check_db(&self) -> Result<()> {
+        self.db.check().await?;
+        Ok(())
+    }
+    /// The last phase of shutdown, once nothing else runs. Deliveries still in flight are returned
+    /// to pending, to be attempted again on the next start, and the Db is closed.
+    pub async fn close(&self) -> Result<()> {
+        let released = self.db.release_deliveries().await?;
+        if released > 0 {
+            info!(released, "released in flight deliveries for the next start");
+        }
+        self.db.close().await?;
+        Ok(())
+    }
+    pub async fn stats(&self) -> Result<Stats> {
+        let stats = self.db.stats().await?;
+        Ok(stats)
+    }
+    pub fn http_client(&self) -> &reqwest::Client {
+        &self.http_client
+    }
+    /// Return the signing key of the given local actor, generating one if it doesn't exist yet.
+    #[instrument(skip(self))]
+    pub async fn actor_key(&self, actor: &Actor) -> Result<PrivateKey> {
+        if let Some(key) = self.db.actor_key(actor).await? {
+            return Ok(key);
+        }
+        let algorithm = self.config.fedi.key_algorithm;
+        debug!(?algorithm, "generating actor key");
+        let key = tokio::task::spawn_blocking(move || PrivateKey::generate(algorithm))
+            .await
+            .map_err(anyhow::Error::from)??;
+        // NIT: Concurrent first use of an actor key races, with the last writer winning. Fine for
+        // now, but a real Db should insert-if-absent.
+        self.db.insert_actor_key(actor, key.clone()).await?;
+        Ok(key)
+    }
+    /// Sign an outgoing request on behalf of the given local actor.
+    pub async fn sign_request(&self, actor: &Actor, request: &mut reqwest::Request) -> Result<()> {
+        let key = self.actor_key(actor).await?;
+        signature::sign_request(request, &self.config.fedi.key_id(actor), &key)?;
+        Ok(())
+    }
+    pub async fn delivery_hosts(&self) -> Result<Vec<DeliveryHost>> {
+        let hosts = self.db.delivery_hosts().await?;
+        Ok(hosts)
+    }
+    pub fn delivery_queue(&self) -> &DeliveryQueue {
+        &self.delivery_queue
+    }
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+    /// Encode the metrics in the Prometheus text format, after updating those read on demand.
+    pub async fn encode_metrics(&self) -> Result<String> {
+        let in_flight = self.db.count_deliveries(DeliveryState::InFlight).await?;
+        let pending = self.db.count_deliveries(DeliveryState::Pending).await?;
+        self.metrics
+            .delivery_queue_depth
+            .set((in_flight + pending) as f64);
+        self.metrics
+            .content_process_queue_depth
+            .set(self.content_process_queue.0.len() as f64);
+        Ok(self.metrics.encode())
+    }
+    /// Queue an activity for delivery to a remote inbox, signed by the given local actor.
+    ///
+    /// The delivery carries the id of the request queueing it, if any, for correlating traces.
+    #[instrument(skip(self, body))]
+    pub async fn deliver(
+        &self,
+        actor: &Actor,
+        inbox: &str,
+        body: String,
+        request_id: Option<RequestId>,
+    ) -> Result<Delivery> {
+        let url = reqwest::Url::parse(inbox).map_err(anyhow::Error::from)?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format_compact!("{host}:{port}"),
+            (Some(host), None) => host.into(),
+            (None, _) => return Err(anyhow!("inbox has no host: {inbox}").into()),
+        };
+        let delivery = self
+            .db
+            .enqueue_delivery(NewDelivery {
+                actor: actor.clone(),
+                inbox: inbox.to_owned(),
+                host,
+                body,
+                request_id,
+            })
+            .await?;
+        debug!(id = %delivery.id, "queued delivery");
+        Ok(delivery)
+    }
+    /// Verify the HTTP Signature of an incoming federated request.
+    pub async fn verify_request(
+        &self,
+        parts: &http::request::Parts,
+        body: &[u8],
+    ) -> Result<SignatureParams, SignatureError> {
+        signature::verify_request(
+            parts,
+            body,
+            self.config.fedi.max_clock_skew(),
+            &self.remote_keys,
+        )
+        .await
+    }
+    /// Open a stream of events for the given user, which must be closed with
+    /// [`Self::close_event_stream`] once the request is done with it.
+    //
+    // TODO: Centralize channel types, don't expose underlying impl - currently Kanal.
+    pub fn open_event_stream(
         &self,
         user_id: UserId,
         req_id: RequestId,
-    ) -> Result<AsyncReceiver<()>> {
+    ) -> Result<AsyncReceiver<UserEvent>> {
         let mut user_events = self.user_events.lock().map_err(|err| anyhow!("{err}"))?;
-        let (_, receiver) = user_events
+        let (sender, receiver) = kanal::bounded_async(USER_EVENTS_CAPACITY);
+        user_events
             .entry(user_id)
             .or_default()
-            .entry(req_id)
-            .or_insert_with(|| {
-                let (sender, receiver) = kanal::bounded_async::<()>(2);
-                (sender, receiver)
-            });
-        Ok(receiver.clone())
+            .insert(req_id, sender);
+        Ok(receiver)
+    }
+    pub fn close_event_stream(&self, user_id: UserId, req_id: RequestId) -> Result<()> {
+        let mut user_events = self.user_events.lock().map_err(|err| anyhow!("{err}"))?;
+        if let Some(streams) = user_events.get_mut(&user_id) {
+            streams.remove(&req_id);
+            if streams.is_empty() {
+                user_events.remove(&user_id);
+            }
+        }
+        Ok(())
     }
 }
 impl fmt::Debug for Summit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // Mostly just a placeholder for eventually lazily recording some maybe useful information
-        // about Summit, event counts, connection counts, etc.
-        f.debug_struct("Summit").finish()
+        f.debug_struct("Summit")
+            .field("posts_created", &self.metrics.posts_created.get())
+            .field("sse_connections", &self.metrics.sse_connections.get())
+            .finish_non_exhaustive()
     }
 }
 
+/// The number of events buffered per event stream, before further events are dropped.
+const USER_EVENTS_CAPACITY: usize = 16;
+/// An event sent to the open event streams of a user.
 #[derive(Debug, Clone)]
-pub struct User {
-    // pub id: CompactString,
-    pub time_zone: TimeZone,
+pub enum UserEvent {
+    Post(Post),
+    /// The unread notification count of the user changed.
+    Notifications {
+        unread: u64,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{db::Author, dev::db::DevDb};
+
+    fn post_by(user: &str, body: &str) -> CreatePost {
+        CreatePost {
+            author: Author {
+                fedi_addr: FediAddr {
+                    user: user.into(),
+                    host: "localhost:3000".into(),
+                },
+            },
+            title: "Title".into(),
+            body: body.into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn notifications_reach_only_the_recipient() {
+        let summit = Summit::new(SummitConfig::default(), Box::<DevDb>::default());
+        // Users are registered by posting, in dev.
+        summit.create_post(post_by("alice", "hi")).await.unwrap();
+        summit.create_post(post_by("bob", "hi")).await.unwrap();
+        assert_eq!(summit.process_queued_content().await, 2);
+        let alice = summit.local_user("alice").await.unwrap().unwrap();
+        let bob = summit.local_user("bob").await.unwrap().unwrap();
+        let alice_events = summit
+            .open_event_stream(alice.id, RequestId::new())
+            .unwrap();
+        let bob_events = summit.open_event_stream(bob.id, RequestId::new()).unwrap();
+
+        summit
+            .create_post(post_by("bob", "@alice@localhost:3000 look"))
+            .await
+            .unwrap();
+        // The notification is sent straight away, posts once processed.
+        assert!(matches!(
+            alice_events.try_recv(),
+            Ok(Some(UserEvent::Notifications { unread: 1 }))
+        ));
+        assert!(matches!(alice_events.try_recv(), Ok(None)));
+        assert_eq!(summit.process_queued_content().await, 1);
+        // Posts go to everyone, the notification only to alice.
+        assert!(matches!(
+            alice_events.try_recv(),
+            Ok(Some(UserEvent::Post(_)))
+        ));
+        assert!(matches!(
+            bob_events.try_recv(),
+            Ok(Some(UserEvent::Post(_)))
+        ));
+        assert!(matches!(bob_events.try_recv(), Ok(None)));
+
+        summit
+            .mark_notifications_read(alice.id, None)
+            .await
+            .unwrap();
+        assert_eq!(summit.unread_notifications(alice.id).await.unwrap(), 0);
+        assert!(matches!(
+            alice_events.try_recv(),
+            Ok(Some(UserEvent::Notifications { unread: 0 }))
+        ));
+    }
+
+    #[test]
+    fn csrf_tokens_are_per_user_and_instance() {
+        let summit = Summit::new(SummitConfig::default(), Box::<DevDb>::default());
+        let (alice, bob) = (UserId::new(), UserId::new());
+        let token = summit.csrf_token(alice);
+        assert!(summit.verify_csrf_token(alice, &token));
+        assert!(!summit.verify_csrf_token(bob, &token));
+        assert!(!summit.verify_csrf_token(alice, ""));
+        let restarted = Summit::new(SummitConfig::default(), Box::<DevDb>::default());
+        assert!(!restarted.verify_csrf_token(alice, &token));
+    }
 }
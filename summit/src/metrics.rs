@@ -0,0 +1,250 @@
+//! Metrics of the running instance, encoded in the Prometheus text format for `/metrics`.
+//!
+//! Deliberately minimal, covering only the counters, gauges and histograms we record.
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+pub mod db;
+
+/// The buckets of latency histograms, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug)]
+pub struct Metrics {
+    /// The latency of http requests, by route, method and status.
+    pub http_requests: Histogram,
+    /// Open `/live` event streams.
+    pub sse_connections: Gauge,
+    /// Pending and in flight deliveries, updated when encoded.
+    pub delivery_queue_depth: Gauge,
+    /// Posts waiting in the content process queue, updated when encoded.
+    pub content_process_queue_depth: Gauge,
+    pub posts_created: Counter,
+    /// The latency of [`crate::db::Db`] calls, by method.
+    pub db_calls: Histogram,
+    /// How far the last tick of the fake user runtime overran its tick rate, in seconds.
+    pub fake_tick_lag: Gauge,
+}
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            http_requests: Histogram::new(&["route", "method", "status"]),
+            sse_connections: Default::default(),
+            delivery_queue_depth: Default::default(),
+            content_process_queue_depth: Default::default(),
+            posts_created: Default::default(),
+            db_calls: Histogram::new(&["method"]),
+            fake_tick_lag: Default::default(),
+        }
+    }
+}
+impl Metrics {
+    /// Encode every metric in the Prometheus text exposition format.
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        self.http_requests.encode(
+            &mut out,
+            "summit_http_request_duration_seconds",
+            "The latency of http requests, by route, method and status.",
+        );
+        self.sse_connections.encode(
+            &mut out,
+            "summit_sse_connections",
+            "Open event stream connections.",
+        );
+        self.delivery_queue_depth.encode(
+            &mut out,
+            "summit_delivery_queue_depth",
+            "Pending and in flight deliveries.",
+        );
+        self.content_process_queue_depth.encode(
+            &mut out,
+            "summit_content_process_queue_depth",
+            "Posts waiting to be processed.",
+        );
+        self.posts_created.encode(
+            &mut out,
+            "summit_posts_created_total",
+            "Posts created on this instance.",
+        );
+        self.db_calls.encode(
+            &mut out,
+            "summit_db_call_duration_seconds",
+            "The latency of Db calls, by method.",
+        );
+        self.fake_tick_lag.encode(
+            &mut out,
+            "summit_fake_tick_lag_seconds",
+            "How far the last fake user runtime tick overran its tick rate.",
+        );
+        out
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+impl Counter {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+    fn encode(&self, out: &mut String, name: &str, help: &str) {
+        write_header(out, name, help, "counter");
+        let _ = writeln!(out, "{name} {}", self.get());
+    }
+}
+
+/// A value which may go up and down, stored as the bits of an `f64`.
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicU64);
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+    pub fn add(&self, delta: f64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + delta).to_bits())
+            });
+    }
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+    /// Increment the gauge until the returned guard is dropped, such as for open connections.
+    pub fn track(&self) -> GaugeGuard<'_> {
+        self.add(1.0);
+        GaugeGuard(self)
+    }
+    fn encode(&self, out: &mut String, name: &str, help: &str) {
+        write_header(out, name, help, "gauge");
+        let _ = writeln!(out, "{name} {}", self.get());
+    }
+}
+pub struct GaugeGuard<'a>(&'a Gauge);
+impl Drop for GaugeGuard<'_> {
+    fn drop(&mut self) {
+        self.0.add(-1.0);
+    }
+}
+
+/// A histogram of durations in [`LATENCY_BUCKETS`], with a series per set of label values.
+#[derive(Debug)]
+pub struct Histogram {
+    label_names: &'static [&'static str],
+    series: Mutex<BTreeMap<Vec<String>, Series>>,
+}
+#[derive(Debug, Default)]
+struct Series {
+    /// Cumulative counts of each bucket.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+impl Histogram {
+    pub fn new(label_names: &'static [&'static str]) -> Self {
+        Self {
+            label_names,
+            series: Default::default(),
+        }
+    }
+    /// Record a duration, with a value for each of the label names in order.
+    pub fn observe(&self, label_values: &[&str], duration: Duration) {
+        debug_assert_eq!(label_values.len(), self.label_names.len());
+        let secs = duration.as_secs_f64();
+        let Ok(mut series) = self.series.lock() else {
+            return;
+        };
+        let key = label_values
+            .iter()
+            .map(|value| (*value).to_owned())
+            .collect();
+        let series = series.entry(key).or_default();
+        for (bucket, le) in series.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if secs <= le {
+                *bucket += 1;
+            }
+        }
+        series.count += 1;
+        series.sum += secs;
+    }
+    fn encode(&self, out: &mut String, name: &str, help: &str) {
+        write_header(out, name, help, "histogram");
+        let Ok(series) = self.series.lock() else {
+            return;
+        };
+        for (label_values, series) in series.iter() {
+            let labels = self
+                .label_names
+                .iter()
+                .zip(label_values)
+                .map(|(name, value)| format!("{name}=\"{}\"", escape_label_value(value)))
+                .collect::<Vec<_>>()
+                .join(",");
+            let sep = if labels.is_empty() { "" } else { "," };
+            for (count, le) in series.buckets.iter().zip(LATENCY_BUCKETS) {
+                let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"{le}\"}} {count}");
+            }
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {}",
+                series.count
+            );
+            let _ = writeln!(out, "{name}_sum{{{labels}}} {}", series.sum);
+            let _ = writeln!(out, "{name}_count{{{labels}}} {}", series.count);
+        }
+    }
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encodes_text_format() {
+        let metrics = Metrics::default();
+        metrics.posts_created.inc();
+        let guard = metrics.sse_connections.track();
+        metrics
+            .db_calls
+            .observe(&["posts"], Duration::from_millis(20));
+        metrics
+            .db_calls
+            .observe(&["posts"], Duration::from_millis(200));
+        let text = metrics.encode();
+        assert!(text.contains("# TYPE summit_posts_created_total counter\n"));
+        assert!(text.contains("summit_posts_created_total 1\n"));
+        assert!(text.contains("summit_sse_connections 1\n"));
+        assert!(text
+            .contains("summit_db_call_duration_seconds_bucket{method=\"posts\",le=\"0.01\"} 0\n"));
+        assert!(text
+            .contains("summit_db_call_duration_seconds_bucket{method=\"posts\",le=\"0.025\"} 1\n"));
+        assert!(text
+            .contains("summit_db_call_duration_seconds_bucket{method=\"posts\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("summit_db_call_duration_seconds_count{method=\"posts\"} 2\n"));
+        drop(guard);
+        assert!(metrics.encode().contains("summit_sse_connections 0\n"));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    content::ContentRefs,
+    date_time::DateTime,
+    db::{
+        Actor, Community, CreatePost, Db, Delivery, DeliveryHost, DeliveryState, FediAddr,
+        LocalUser, NewDelivery, NewNotification, Notification, Post, Preferences, Profile, Result,
+        Stats,
+    },
+    fedi::signature::PrivateKey,
+    metrics::Metrics,
+    uuid::{DeliveryId, NotificationId, PostId, UserId},
+};
+use async_trait::async_trait;
+use std::{sync::Arc, time::Instant};
+
+/// Time the given call of the inner [`Db`], recording it by method name.
+macro_rules! measure {
+    ($self:ident.$method:ident($($arg:expr),*)) => {{
+        let start = Instant::now();
+        let res = $self.db.$method($($arg),*).await;
+        $self
+            .metrics
+            .db_calls
+            .observe(&[stringify!($method)], start.elapsed());
+        res
+    }};
+}
+
+/// A [`Db`] recording the latency of every call of the wrapped `Db` in [`Metrics::db_calls`].
+#[derive(Debug)]
+pub struct MeasuredDb {
+    db: Box<dyn Db>,
+    metrics: Arc<Metrics>,
+}
+impl MeasuredDb {
+    pub fn new(db: Box<dyn Db>, metrics: Arc<Metrics>) -> Self {
+        Self { db, metrics }
+    }
+}
+#[async_trait]
+impl Db for MeasuredDb {
+    async fn check(&self) -> Result<()> {
+        measure!(self.check())
+    }
+    async fn close(&self) -> Result<()> {
+        measure!(self.close())
+    }
+    async fn posts(&self, before: Option<PostId>, limit: usize) -> Result<Vec<Post>> {
+        measure!(self.posts(before, limit))
+    }
+    async fn post(&self, id: PostId) -> Result<Option<Post>> {
+        measure!(self.post(id))
+    }
+    async fn create_post(&self, create_post: CreatePost, refs: ContentRefs) -> Result<Post> {
+        measure!(self.create_post(create_post, refs))
+    }
+    async fn tag_posts(&self, tag: &str, limit: usize) -> Result<Vec<Post>> {
+        measure!(self.tag_posts(tag, limit))
+    }
+    async fn local_actor(&self, name: &str) -> Result<Option<Actor>> {
+        measure!(self.local_actor(name))
+    }
+    async fn local_user(&self, name: &str) -> Result<Option<LocalUser>> {
+        measure!(self.local_user(name))
+    }
+    async fn community(&self, name: &str) -> Result<Option<Community>> {
+        measure!(self.community(name))
+    }
+    async fn preferences(&self, user_id: UserId) -> Result<Preferences> {
+        measure!(self.preferences(user_id))
+    }
+    async fn set_preferences(&self, user_id: UserId, preferences: Preferences) -> Result<()> {
+        measure!(self.set_preferences(user_id, preferences))
+    }
+    async fn profile(&self, addr: &FediAddr) -> Result<Option<Profile>> {
+        measure!(self.profile(addr))
+    }
+    async fn user_posts(
+        &self,
+        author: &FediAddr,
+        before: Option<PostId>,
+        limit: usize,
+    ) -> Result<Vec<Post>> {
+        measure!(self.user_posts(author, before, limit))
+    }
+    async fn stats(&self) -> Result<Stats> {
+        measure!(self.stats())
+    }
+    async fn actor_key(&self, actor: &Actor) -> Result<Option<PrivateKey>> {
+        measure!(self.actor_key(actor))
+    }
+    async fn insert_actor_key(&self, actor: &Actor, key: PrivateKey) -> Result<()> {
+        measure!(self.insert_actor_key(actor, key))
+    }
+    async fn enqueue_delivery(&self, new_delivery: NewDelivery) -> Result<Delivery> {
+        measure!(self.enqueue_delivery(new_delivery))
+    }
+    async fn claim_deliveries(&self, now: DateTime, limit: usize) -> Result<Vec<Delivery>> {
+        measure!(self.claim_deliveries(now, limit))
+    }
+    async fn release_deliveries(&self) -> Result<u64> {
+        measure!(self.release_deliveries())
+    }
+    async fn complete_delivery(&self, id: DeliveryId) -> Result<()> {
+        measure!(self.complete_delivery(id))
+    }
+    async fn fail_delivery(
+        &self,
+        id: DeliveryId,
+        error: String,
+        retry_on: Option<DateTime>,
+    ) -> Result<()> {
+        measure!(self.fail_delivery(id, error, retry_on))
+    }
+    async fn deliveries(&self, state: DeliveryState) -> Result<Vec<Delivery>> {
+        measure!(self.deliveries(state))
+    }
+    async fn count_deliveries(&self, state: DeliveryState) -> Result<u64> {
+        measure!(self.count_deliveries(state))
+    }
+    async fn delivery_host(&self, host: &str) -> Result<Option<DeliveryHost>> {
+        measure!(self.delivery_host(host))
+    }
+    async fn delivery_hosts(&self) -> Result<Vec<DeliveryHost>> {
+        measure!(self.delivery_hosts())
+    }
+    async fn record_host_attempt(
+        &self,
+        host: &str,
+        success: bool,
+        dead_after: u32,
+    ) -> Result<DeliveryHost> {
+        measure!(self.record_host_attempt(host, success, dead_after))
+    }
+    async fn create_notification(&self, new_notification: NewNotification) -> Result<Notification> {
+        measure!(self.create_notification(new_notification))
+    }
+    async fn notifications(
+        &self,
+        recipient: UserId,
+        before: Option<NotificationId>,
+        limit: usize,
+    ) -> Result<Vec<Notification>> {
+        measure!(self.notifications(recipient, before, limit))
+    }
+    async fn unread_notifications(&self, recipient: UserId) -> Result<u64> {
+        measure!(self.unread_notifications(recipient))
+    }
+    async fn mark_notifications_read(
+        &self,
+        recipient: UserId,
+        ids: Option<&[NotificationId]>,
+    ) -> Result<()> {
+        measure!(self.mark_notifications_read(recipient, ids))
+    }
+    async fn insert_api_token(&self, user_id: UserId, token_hash: Vec<u8>) -> Result<()> {
+        measure!(self.insert_api_token(user_id, token_hash))
+    }
+    async fn api_token_user(&self, token_hash: &[u8]) -> Result<Option<LocalUser>> {
+        measure!(self.api_token_user(token_hash))
+    }
+}
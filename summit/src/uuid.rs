@@ -1,7 +1,36 @@
 //! A general implementation of [`Uuid`] and wrapper types like [`RequestId`] and [`UserId`].
-use compact_str::CompactString;
+use crate::date_time::DateTime;
+use compact_str::{format_compact, CompactString};
 use data_encoding::BASE64URL_NOPAD;
-use std::fmt;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef},
+    Decode, Encode, Sqlite, Type,
+};
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+/// The length of the base64url form, as written by `Display`.
+const ENCODED_LEN: usize = 22;
+/// The length of the canonical `8-4-4-4-12` hyphenated hex form.
+const HYPHENATED_LEN: usize = 36;
+/// The latest timestamp that fits in the 48 bits UUIDv7 gives it.
+const MAX_TIMESTAMP_MILLIS: i64 = (1 << 48) - 1;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum UuidError {
+    #[error(
+        "invalid length {0}, expected {ENCODED_LEN} base64url or {HYPHENATED_LEN} hyphenated \
+         characters"
+    )]
+    InvalidLength(usize),
+    #[error("invalid base64url encoding")]
+    InvalidBase64,
+    #[error("invalid hyphenated form")]
+    InvalidHyphenated,
+}
 
 /// A general purpose centralized uuid, currently using UUIDv7, and encoding itself with  
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -23,12 +52,106 @@ impl Uuid {
         // inlined anyway.. need to check, because i'm curious.
         BASE64URL_NOPAD.encode(self.0.as_ref()).into()
     }
+    /// The canonical `8-4-4-4-12` hyphenated hex form, as used by most other uuid tooling.
+    pub fn hyphenated(&self) -> CompactString {
+        format_compact!("{}", self.0)
+    }
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        self.0.as_bytes()
+    }
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        Self(uuid7::Uuid::from(bytes))
+    }
+    /// The millisecond precision time this id was created at.
+    pub fn timestamp(&self) -> DateTime {
+        let mut millis = [0u8; 8];
+        millis[2..].copy_from_slice(&self.as_bytes()[..6]);
+        DateTime::from_timestamp_millis(i64::from_be_bytes(millis))
+            .expect("48 bit timestamps are within range")
+    }
+    /// The lowest id that could be created at `at`, ordering before every id created at or after
+    /// it. Such as for a "since" cursor.
+    ///
+    /// Times outside of the 48 bit millisecond range of UUIDv7 are clamped.
+    pub fn min_at(at: DateTime) -> Self {
+        // Version 7, and the `10` variant, with every random bit unset.
+        Self::at(at, [0x70, 0x00, 0x80, 0, 0, 0, 0, 0, 0, 0])
+    }
+    /// The highest id that could be created at `at`, ordering after every id created at or before
+    /// it. Such as for a "before" cursor.
+    ///
+    /// Times outside of the 48 bit millisecond range of UUIDv7 are clamped.
+    pub fn max_at(at: DateTime) -> Self {
+        // Version 7, and the `10` variant, with every random bit set.
+        Self::at(
+            at,
+            [0x7F, 0xFF, 0xBF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+        )
+    }
+    fn at(at: DateTime, tail: [u8; 10]) -> Self {
+        let millis = at.timestamp_millis().clamp(0, MAX_TIMESTAMP_MILLIS);
+        let mut bytes = [0u8; 16];
+        bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+        bytes[6..].copy_from_slice(&tail);
+        Self::from_bytes(bytes)
+    }
+}
+impl FromStr for Uuid {
+    type Err = UuidError;
+    /// Parse either the base64url form written by `Display`, or the hyphenated form.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.len() {
+            ENCODED_LEN => {
+                let mut bytes = [0u8; 16];
+                // Non-canonical trailing bits are rejected by the encoding, so every id has
+                // exactly one base64url form.
+                BASE64URL_NOPAD
+                    .decode_mut(s.as_bytes(), &mut bytes)
+                    .map_err(|_| UuidError::InvalidBase64)?;
+                Ok(Self::from_bytes(bytes))
+            },
+            HYPHENATED_LEN => s
+                .parse::<uuid7::Uuid>()
+                .map(Self)
+                .map_err(|_| UuidError::InvalidHyphenated),
+            len => Err(UuidError::InvalidLength(len)),
+        }
+    }
 }
 impl fmt::Display for Uuid {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.encode())
     }
 }
+impl Serialize for Uuid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.encode())
+    }
+}
+impl<'de> Deserialize<'de> for Uuid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Owned rather than borrowed, as url decoded strings such as query params may be owned.
+        let s = CompactString::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+/// Stored as a 16 byte blob, which sorts the same as the uuid itself.
+impl Type<Sqlite> for Uuid {
+    fn type_info() -> SqliteTypeInfo {
+        <&[u8] as Type<Sqlite>>::type_info()
+    }
+}
+impl<'q> Encode<'q, Sqlite> for Uuid {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        <Vec<u8> as Encode<'q, Sqlite>>::encode(self.as_bytes().to_vec(), args)
+    }
+}
+impl<'r> Decode<'r, Sqlite> for Uuid {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        let bytes = <&[u8] as Decode<'r, Sqlite>>::decode(value)?;
+        Ok(Self::from_bytes(bytes.try_into()?))
+    }
+}
 
 macro_rules! uuid_impl {
     {
@@ -36,12 +159,23 @@ macro_rules! uuid_impl {
         pub struct $name:ident;
     } => {
         $(#[$doc])*
-        #[derive(Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+        #[derive(
+            Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize,
+        )]
+        #[serde(transparent)]
         pub struct $name(pub Uuid);
         impl $name {
             pub fn new() -> Self {
                 Self(Uuid::new())
             }
+            /// See [`Uuid::min_at`].
+            pub fn min_at(at: DateTime) -> Self {
+                Self(Uuid::min_at(at))
+            }
+            /// See [`Uuid::max_at`].
+            pub fn max_at(at: DateTime) -> Self {
+                Self(Uuid::max_at(at))
+            }
         }
         impl std::ops::Deref for $name {
             type Target = Uuid;
@@ -64,6 +198,27 @@ macro_rules! uuid_impl {
                 write!(f, "{}", self.0)
             }
         }
+        impl FromStr for $name {
+            type Err = UuidError;
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse().map(Self)
+            }
+        }
+        impl Type<Sqlite> for $name {
+            fn type_info() -> SqliteTypeInfo {
+                <Uuid as Type<Sqlite>>::type_info()
+            }
+        }
+        impl<'q> Encode<'q, Sqlite> for $name {
+            fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+                self.0.encode_by_ref(args)
+            }
+        }
+        impl<'r> Decode<'r, Sqlite> for $name {
+            fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+                Uuid::decode(value).map(Self)
+            }
+        }
     };
 }
 
@@ -73,3 +228,109 @@ uuid_impl! {
 uuid_impl! {
     pub struct UserId;
 }
+uuid_impl! {
+    pub struct DeliveryId;
+}
+uuid_impl! {
+    pub struct PostId;
+}
+uuid_impl! {
+    pub struct NotificationId;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    macro_rules! round_trip_tests {
+        ($($test:ident: $name:ident,)*) => {$(
+            proptest! {
+                #[test]
+                fn $test(bytes in any::<[u8; 16]>()) {
+                    let id = $name(Uuid::from_bytes(bytes));
+                    prop_assert_eq!(id.to_string().parse::<$name>(), Ok(id));
+                    prop_assert_eq!(id.hyphenated().parse::<$name>(), Ok(id));
+                    let json = serde_json::to_string(&id).unwrap();
+                    prop_assert_eq!(serde_json::from_str::<$name>(&json).unwrap(), id);
+                }
+            }
+        )*};
+    }
+    round_trip_tests! {
+        request_id_round_trips: RequestId,
+        user_id_round_trips: UserId,
+        delivery_id_round_trips: DeliveryId,
+        post_id_round_trips: PostId,
+        notification_id_round_trips: NotificationId,
+    }
+
+    proptest! {
+        #[test]
+        fn bounds_keep_the_timestamp(millis in 0..=MAX_TIMESTAMP_MILLIS) {
+            let at = DateTime::from_timestamp_millis(millis).unwrap();
+            let (min, max) = (Uuid::min_at(at), Uuid::max_at(at));
+            prop_assert_eq!(min.timestamp(), at);
+            prop_assert_eq!(max.timestamp(), at);
+            prop_assert!(min < max);
+        }
+    }
+
+    #[test]
+    fn ids_order_within_their_millisecond() {
+        let ids = (0..10_000).map(|_| Uuid::new()).collect::<Vec<_>>();
+        assert!(
+            ids.windows(2).all(|w| w[0] < w[1]),
+            "ids are strictly increasing"
+        );
+        let mut shared_millis = 0;
+        for w in ids.windows(2) {
+            if w[0].timestamp() == w[1].timestamp() {
+                shared_millis += 1;
+            }
+        }
+        assert!(
+            shared_millis > 0,
+            "some ids were made in the same millisecond"
+        );
+        for id in &ids {
+            let at = id.timestamp();
+            assert!(Uuid::min_at(at) <= *id && *id <= Uuid::max_at(at));
+            let next = DateTime::from_timestamp_millis(at.timestamp_millis() + 1).unwrap();
+            assert!(*id < Uuid::min_at(next));
+        }
+    }
+
+    #[test]
+    fn bounds_clamp_out_of_range_times() {
+        let before_epoch = DateTime::from_timestamp_millis(-1).unwrap();
+        assert_eq!(Uuid::min_at(before_epoch).timestamp().timestamp_millis(), 0);
+        let far_future = DateTime::from_timestamp_millis(MAX_TIMESTAMP_MILLIS + 1).unwrap();
+        assert_eq!(
+            Uuid::max_at(far_future).timestamp().timestamp_millis(),
+            MAX_TIMESTAMP_MILLIS
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("".parse::<Uuid>(), Err(UuidError::InvalidLength(0)));
+        assert_eq!(
+            "AAAAAAAAAAAAAAAAAAAAA!".parse::<Uuid>(),
+            Err(UuidError::InvalidBase64)
+        );
+        // Non-zero trailing bits, which would otherwise alias `AAAAAAAAAAAAAAAAAAAAAA`.
+        assert_eq!(
+            "AAAAAAAAAAAAAAAAAAAAAB".parse::<Uuid>(),
+            Err(UuidError::InvalidBase64)
+        );
+        assert_eq!(
+            "0189a1b2-c3d4-7e5f-8a9b-0c1d2e3f4g5h".parse::<Uuid>(),
+            Err(UuidError::InvalidHyphenated)
+        );
+        let id = "0189a1b2-c3d4-7e5f-8a9b-0c1d2e3f4a5b"
+            .parse::<Uuid>()
+            .unwrap();
+        assert_eq!(id.hyphenated(), "0189a1b2-c3d4-7e5f-8a9b-0c1d2e3f4a5b");
+    }
+}
@@ -1,63 +1,261 @@
 use crate::{
     web::{
-        extension::request_id::{self, RequestIdLayer},
+        extension::{
+            metrics,
+            request_id::{self, RequestIdLayer},
+            security_headers::{self, SecurityHeaders},
+            viewer,
+        },
         shutdown::ShutdownSignal,
     },
     Summit,
 };
-use axum::{routing::get, Router};
+use axum::{
+    middleware,
+    routing::{get, post, MethodRouter},
+    Router,
+};
 use clap::Parser;
-use std::sync::Arc;
-use tower_http::trace::TraceLayer;
-use tracing::info;
+use std::{sync::Arc, time::Duration};
+use tower_http::{
+    compression::{
+        predicate::{Predicate, SizeAbove},
+        CompressionLayer,
+    },
+    trace::TraceLayer,
+};
+use tracing::{error, info, warn};
 
-mod extension;
+mod compression;
+pub mod error;
+pub mod extension;
 pub mod handler;
-mod shutdown;
+pub mod shutdown;
 pub mod template;
 
-#[derive(Parser, Debug, Default)]
+#[derive(Parser, Debug)]
 pub struct ServeConfig {
     #[arg(long, default_value = "127.0.0.1")]
     pub host: String,
     #[arg(long, default_value_t = 3000)]
     pub port: u16,
+    /// The minimum size of html responses to compress, as smaller responses gain little.
+    ///
+    /// In bytes.
+    #[arg(long, default_value_t = 1024)]
+    pub compress_min_size: u16,
+    /// Compress the `/live` event stream when the client accepts it, flushing after each event.
+    #[arg(long)]
+    pub compress_live: bool,
+    /// Send `Strict-Transport-Security` with this max age, in seconds.
+    ///
+    /// Only set when served over https, such as behind a terminating proxy.
+    #[arg(long)]
+    pub hsts_max_age: Option<u32>,
+    /// Send `Strict-Transport-Security` with `includeSubDomains`, if `--hsts-max-age` is set.
+    ///
+    /// Only set when every subdomain is served over https too.
+    #[arg(long)]
+    pub hsts_include_subdomains: bool,
+    /// Use the `X-Request-Id` of incoming requests, rather than assigning a new id.
+    ///
+    /// Only set behind a proxy which sets or strips the header. Ids longer than 128 characters or
+    /// with other than visible ascii are still replaced.
+    #[arg(long)]
+    pub trust_request_id: bool,
+    /// Serve `/metrics`, `/drain` and `/admin/deliveries` on a separate admin listener at this
+    /// address, such as `127.0.0.1:9100`. Otherwise none are served.
+    #[arg(long)]
+    pub admin_addr: Option<String>,
+    /// How long to keep serving after a shutdown signal, while failing readiness checks, so that
+    /// load balancers stop routing here before connections are closed.
+    ///
+    /// In milliseconds.
+    #[arg(long, default_value_t = 0)]
+    pub drain_ms: u64,
+    /// How long each phase of shutdown may take, after draining, such as waiting on open
+    /// connections or in flight deliveries. Whatever is left is then dropped or persisted.
+    ///
+    /// In milliseconds.
+    #[arg(long, default_value_t = 10_000)]
+    pub shutdown_timeout_ms: u64,
+}
+impl Default for ServeConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".into(),
+            port: 3000,
+            compress_min_size: 1024,
+            compress_live: false,
+            hsts_max_age: None,
+            hsts_include_subdomains: false,
+            trust_request_id: false,
+            admin_addr: None,
+            drain_ms: 0,
+            shutdown_timeout_ms: 10_000,
+        }
+    }
 }
 
+/// Serve the site until the shutdown signal, then stop accepting connections and wait on those
+/// still open for up to [`ServeConfig::shutdown_timeout_ms`].
 pub async fn serve(
     config: ServeConfig,
     summit: Arc<Summit>,
+    shutdown_signal: ShutdownSignal,
     // TODO: Probably worth it to make a server instance and include `with_fake()` to modify the
     // router, avoiding this nonsense.
     #[cfg(any(test, feature = "dev"))] fake: Arc<crate::dev::fake::user::FakeUsers>,
 ) -> Result<(), hyper::Error> {
-    let shutdown_signal = ShutdownSignal::new().await;
+    let live =
+        get(handler::live::live_handler).with_state((summit.clone(), shutdown_signal.clone()));
+    let live = if config.compress_live {
+        live.layer(middleware::from_fn(compression::compress_event_stream))
+    } else {
+        live
+    };
     let app = Router::new()
         .route("/c/", get(handler::community::handler))
+        .route("/c/:name", get(handler::community::community_handler))
+        .route("/c/:name/inbox", post(handler::inbox::handler))
+        .route("/c/:name/feed.atom", get(handler::feed::community_atom))
+        .route("/c/:name/feed.rss", get(handler::feed::community_rss))
+        .route("/u/:name", get(handler::profile::handler))
+        .route("/u/:name/inbox", post(handler::inbox::handler))
+        .route("/u/:name/feed.atom", get(handler::feed::user_atom))
+        .route("/u/:name/feed.rss", get(handler::feed::user_rss))
+        .route("/inbox", post(handler::inbox::handler))
+        .route("/p/:id", get(handler::post::handler))
+        .route("/t/:tag", get(handler::tag::handler))
+        .route("/t/:tag/feed.atom", get(handler::feed::tag_atom))
+        .route("/t/:tag/feed.rss", get(handler::feed::tag_rss))
+        .route("/notifications", get(handler::notifications::handler))
+        .route(
+            "/notifications/read",
+            post(handler::notifications::read_handler),
+        )
         .route(
-            "/live",
-            get(handler::live::live_handler).with_state((summit.clone(), shutdown_signal.clone())),
+            "/preferences",
+            get(handler::preferences::handler).post(handler::preferences::save_handler),
         )
-        .route("/static/*key", get(handler::static_assets::serve_asset))
-        .with_state(summit);
+        .route("/live", live)
+        .route("/healthz", get(handler::health::healthz))
+        .route(
+            "/readyz",
+            get(handler::health::readyz).with_state((summit.clone(), shutdown_signal.clone())),
+        )
+        .route("/static/*key", static_assets())
+        .route("/.well-known/webfinger", get(handler::webfinger::handler))
+        .route(
+            "/.well-known/nodeinfo",
+            get(handler::nodeinfo::discovery_handler),
+        )
+        .route("/nodeinfo/2.1", get(handler::nodeinfo::handler))
+        .nest("/api/v1", handler::api::v1::router());
+    #[cfg(any(test, feature = "dev"))]
+    let app = app
+        .route("/dev/login/:name", get(handler::dev::login::login_handler))
+        // Tokens are created for the viewer, who is only known in dev until real sessions exist.
+        .route(
+            "/preferences/tokens",
+            post(handler::preferences::create_token_handler),
+        );
+    if let Some(admin_addr) = &config.admin_addr {
+        serve_admin(admin_addr, summit.clone(), shutdown_signal.clone());
+    }
+    let app = app.with_state(summit.clone());
     #[cfg(feature = "local_dev")]
     let app = app.route(
         "/dev/watch-restart",
-        get(handler::dev::restart::restart_handler).with_state(shutdown_signal.clone()),
+        get(handler::dev::restart::restart_handler).with_state((
+            shutdown_signal.clone(),
+            handler::dev::reload::DevReload::watch(shutdown_signal.clone()),
+        )),
     );
     // FIXME: I think this doesn't work with Axum handlers. Need to move this to whatever handler
     // consumes this in the future, most likely some admin endpoint.
     #[cfg(any(test, feature = "dev"))]
     let app = app.with_state(fake);
     let app = app
+        .fallback(error::fallback)
+        .layer(middleware::from_fn(error::error_pages))
+        .layer(
+            CompressionLayer::new()
+                .compress_when(SizeAbove::new(config.compress_min_size).and(compression::IsHtml)),
+        )
+        .layer(middleware::from_fn(compression::vary_html))
+        .layer(middleware::from_fn_with_state(
+            summit.clone(),
+            viewer::resolve_viewer,
+        ))
+        .layer(middleware::from_fn_with_state(
+            summit,
+            metrics::track_requests,
+        ))
+        .layer(middleware::from_fn_with_state(
+            SecurityHeaders::new(config.hsts_max_age, config.hsts_include_subdomains),
+            security_headers::security_headers,
+        ))
         .layer(TraceLayer::new_for_http().make_span_with(request_id::trace_layer_span_with))
-        .layer(RequestIdLayer);
+        .layer(RequestIdLayer {
+            trust_incoming: config.trust_request_id,
+        });
 
-    let ServeConfig { host, port } = config;
+    let ServeConfig {
+        host,
+        port,
+        shutdown_timeout_ms,
+        ..
+    } = config;
     let listen_addr = format!("{host}:{port}");
     info!(listen_addr, "starting server..");
-    axum::Server::bind(&listen_addr.parse().unwrap())
+    let server = axum::Server::bind(&listen_addr.parse().unwrap())
         .serve(app.into_make_service())
-        .with_graceful_shutdown(async move { shutdown_signal.recv().await })
-        .await
+        .with_graceful_shutdown({
+            let shutdown_signal = shutdown_signal.clone();
+            async move { shutdown_signal.recv().await }
+        });
+    let shutdown_timeout = Duration::from_millis(shutdown_timeout_ms);
+    tokio::select! {
+        res = server => res,
+        () = async {
+            shutdown_signal.recv().await;
+            tokio::time::sleep(shutdown_timeout).await;
+        } => {
+            warn!(?shutdown_timeout, "connections still open after shutdown timeout, dropping them");
+            Ok(())
+        }
+    }
+}
+fn static_assets<S: Clone + Send + Sync + 'static>() -> MethodRouter<S> {
+    #[cfg(not(feature = "local_dev"))]
+    return get(handler::static_assets::serve_asset);
+    #[cfg(feature = "local_dev")]
+    return get(handler::dev::reload::serve_asset);
+}
+/// Serve `/metrics`, `/drain` and admin pages on a separate listener, such as one only reachable
+/// internally, as there are no admin users to check for yet.
+///
+/// It stops along with the runtime, once the main server has shut down.
+fn serve_admin(admin_addr: &str, summit: Arc<Summit>, shutdown_signal: ShutdownSignal) {
+    let app = Router::new()
+        .route("/metrics", get(handler::metrics::handler))
+        .route(
+            "/admin/deliveries",
+            get(handler::admin::deliveries::handler),
+        )
+        .route("/static/*key", static_assets())
+        .with_state(summit)
+        .route(
+            "/drain",
+            post(handler::health::drain).with_state(shutdown_signal),
+        );
+    info!(admin_addr, "starting admin server..");
+    let server = axum::Server::bind(&admin_addr.parse().unwrap()).serve(app.into_make_service());
+    #[allow(clippy::let_underscore_future)]
+    let _ = tokio::spawn(async move {
+        if let Err(err) = server.await {
+            error!(?err, "admin server failed");
+        }
+    });
 }
@@ -0,0 +1,221 @@
+//! Compression of dynamic responses, negotiated by `Accept-Encoding`.
+//!
+//! Html pages are compressed by tower-http's `CompressionLayer`, with [`IsHtml`]. The `/live`
+//! event stream is instead compressed by [`compress_event_stream`], which flushes after every
+//! event so that compression never holds an event back.
+use axum::{
+    body::{self, Bytes, HttpBody, StreamBody},
+    http::{header, HeaderMap, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use flate2::{write::GzEncoder, Compression};
+use std::io::{self, Write};
+use tower_http::compression::predicate::Predicate;
+use tracing::error;
+
+/// The encodings negotiated by [`Encoding::negotiate`], as supported by both precompressed static
+/// assets and the event stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Identity,
+}
+impl Encoding {
+    /// Choose the encoding the client accepts with the highest q-value, preferring brotli over
+    /// gzip on ties, and falling back to identity.
+    pub fn negotiate(headers: &HeaderMap) -> Self {
+        let (mut brotli, mut gzip, mut any) = (None, None, None);
+        let codings = headers
+            .get_all(header::ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for coding in codings {
+            let mut params = coding.split(';');
+            let name = params.next().unwrap_or_default().trim();
+            let q = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if name.eq_ignore_ascii_case("br") {
+                brotli = Some(q);
+            } else if name.eq_ignore_ascii_case("gzip") || name.eq_ignore_ascii_case("x-gzip") {
+                gzip = Some(q);
+            } else if name == "*" {
+                any = Some(q);
+            }
+        }
+        let brotli = brotli.or(any).unwrap_or(0.0);
+        let gzip = gzip.or(any).unwrap_or(0.0);
+        if brotli > 0.0 && brotli >= gzip {
+            Self::Brotli
+        } else if gzip > 0.0 {
+            Self::Gzip
+        } else {
+            Self::Identity
+        }
+    }
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        match self {
+            Self::Brotli => Some("br"),
+            Self::Gzip => Some("gzip"),
+            Self::Identity => None,
+        }
+    }
+}
+
+/// Compress only html, as rendered by the `Template` responder.
+///
+/// Other responses are either already compressed, such as static assets, or streamed, such as
+/// the event stream.
+#[derive(Debug, Clone, Copy)]
+pub struct IsHtml;
+impl Predicate for IsHtml {
+    fn should_compress<B>(&self, response: &http::Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map_or(false, |content_type| {
+                content_type.starts_with(mime::TEXT_HTML.essence_str())
+            })
+    }
+}
+
+/// Middleware marking html responses as varying by `Accept-Encoding`, as `CompressionLayer` may
+/// or may not have compressed them depending on it.
+pub async fn vary_html<B>(req: Request<B>, next: Next<B>) -> Response {
+    let mut res = next.run(req).await;
+    if IsHtml.should_compress(&res) {
+        res.headers_mut().append(
+            header::VARY,
+            HeaderValue::from_static(header::ACCEPT_ENCODING.as_str()),
+        );
+    }
+    res
+}
+
+/// Middleware compressing the event stream, flushing the encoder after every chunk of the body so
+/// each event reaches the client as soon as it's sent.
+pub async fn compress_event_stream<B>(req: Request<B>, next: Next<B>) -> Response {
+    let encoding = Encoding::negotiate(req.headers());
+    let res = next.run(req).await;
+    let Some(content_encoding) = encoding.content_encoding() else {
+        return res;
+    };
+    if res.headers().contains_key(header::CONTENT_ENCODING) {
+        return res;
+    }
+    let (mut parts, mut body) = res.into_parts();
+    parts.headers.insert(
+        header::CONTENT_ENCODING,
+        HeaderValue::from_static(content_encoding),
+    );
+    parts.headers.append(
+        header::VARY,
+        HeaderValue::from_static(header::ACCEPT_ENCODING.as_str()),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let stream = async_stream::stream! {
+        let mut encoder = StreamEncoder::new(encoding);
+        while let Some(chunk) = body.data().await {
+            let res = chunk
+                .map_err(axum::Error::new)
+                .and_then(|chunk| encoder.encode(&chunk).map_err(axum::Error::new));
+            match res {
+                Ok(encoded) => yield Ok(encoded),
+                Err(err) => {
+                    error!(?err, "failed to compress event stream");
+                    yield Err(err);
+                    return;
+                }
+            }
+        }
+    };
+    Response::from_parts(parts, body::boxed(StreamBody::new(stream)))
+}
+
+/// An encoder which flushes after every chunk, emitting everything written so far.
+enum StreamEncoder {
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    Gzip(GzEncoder<Vec<u8>>),
+    Identity,
+}
+impl StreamEncoder {
+    /// Brotli's quality, low enough to keep up with frequent small writes.
+    const BROTLI_QUALITY: u32 = 5;
+    const BROTLI_WINDOW: u32 = 22;
+
+    fn new(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Brotli => Self::Brotli(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                4096,
+                Self::BROTLI_QUALITY,
+                Self::BROTLI_WINDOW,
+            ))),
+            Encoding::Gzip => Self::Gzip(GzEncoder::new(Vec::new(), Compression::fast())),
+            Encoding::Identity => Self::Identity,
+        }
+    }
+    fn encode(&mut self, chunk: &[u8]) -> io::Result<Bytes> {
+        let buf = match self {
+            Self::Brotli(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            },
+            Self::Gzip(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            },
+            Self::Identity => return Ok(Bytes::copy_from_slice(chunk)),
+        };
+        Ok(Bytes::from(std::mem::take(buf)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::GzDecoder;
+
+    #[test]
+    fn negotiate_encoding() {
+        let negotiate = |accept_encoding: Option<&'static str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(accept_encoding) = accept_encoding {
+                headers.insert(
+                    header::ACCEPT_ENCODING,
+                    HeaderValue::from_static(accept_encoding),
+                );
+            }
+            Encoding::negotiate(&headers)
+        };
+        assert_eq!(negotiate(None), Encoding::Identity);
+        assert_eq!(negotiate(Some("gzip, deflate, br")), Encoding::Brotli);
+        assert_eq!(negotiate(Some("gzip, deflate")), Encoding::Gzip);
+        assert_eq!(negotiate(Some("br;q=0.5, gzip")), Encoding::Gzip);
+        assert_eq!(negotiate(Some("br;q=0, *")), Encoding::Gzip);
+        assert_eq!(negotiate(Some("*;q=0")), Encoding::Identity);
+        assert_eq!(negotiate(Some("identity")), Encoding::Identity);
+    }
+    #[test]
+    fn flushes_every_chunk() {
+        let mut encoder = StreamEncoder::new(Encoding::Gzip);
+        let mut decoder = GzDecoder::new(Vec::new());
+        for event in ["event: a\ndata: 1\n\n", "event: b\ndata: 2\n\n"] {
+            let encoded = encoder.encode(event.as_bytes()).unwrap();
+            decoder.write_all(&encoded).unwrap();
+            decoder.flush().unwrap();
+            // Everything sent so far decodes, without waiting on later chunks.
+            assert!(decoder.get_ref().ends_with(event.as_bytes()));
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! Errors of web handlers, and the error pages shown for them.
+use crate::{
+    db::DbError,
+    uuid::RequestId,
+    web::{
+        extension::{request_id::RequestIdHeader, viewer::Viewer},
+        template::{meta::PageMeta, Template},
+    },
+};
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequest, FromRequestParts, Path, Query},
+    http::{header, request::Parts, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Form,
+};
+use sailfish::TemplateOnce;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+use tracing::{error, warn};
+
+/// An error of a web handler, responding with its status code and an error page.
+#[derive(Debug, Error)]
+pub enum WebError {
+    #[error("not found")]
+    NotFound,
+    /// The page requires a signed in viewer.
+    #[error("unauthorized")]
+    Unauthorized,
+    #[error("forbidden: {0}")]
+    Forbidden(&'static str),
+    #[error("bad request: {0}")]
+    BadRequest(String),
+    /// An extractor rejected the request, such as a malformed path or form.
+    #[error("rejected request: {1}")]
+    Rejected(StatusCode, String),
+    #[error(transparent)]
+    Summit(#[from] crate::Error),
+}
+impl From<DbError> for WebError {
+    fn from(err: DbError) -> Self {
+        Self::Summit(err.into())
+    }
+}
+impl WebError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Rejected(status, _) => *status,
+            Self::Summit(crate::Error::Signature(_)) => StatusCode::UNAUTHORIZED,
+            Self::Summit(crate::Error::Other(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+impl IntoResponse for WebError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            error!(err = ?self, "failed to serve request");
+        } else {
+            warn!(err = ?self, %status, "rejected request");
+        }
+        // The page itself is rendered by `error_pages`, which knows the request.
+        let mut res = status.into_response();
+        let detail = match self {
+            Self::Forbidden(detail) => Some(detail.to_owned()),
+            Self::BadRequest(detail) | Self::Rejected(_, detail) => Some(detail),
+            _ => None,
+        };
+        if let Some(detail) = detail {
+            res.extensions_mut().insert(ErrorDetail(detail));
+        }
+        res
+    }
+}
+/// Why a request was rejected, carried from a [`WebError`] response to its [`ErrorPage`].
+#[derive(Debug, Clone)]
+struct ErrorDetail(String);
+
+/// A [`Path`] rejecting with a [`WebError`].
+pub struct WebPath<T>(pub T);
+#[async_trait]
+impl<S, T> FromRequestParts<S> for WebPath<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Send,
+{
+    type Rejection = WebError;
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::from_request_parts(parts, state)
+            .await
+            .map_err(|err| WebError::Rejected(err.status(), err.body_text()))?;
+        Ok(Self(value))
+    }
+}
+/// A [`Query`] rejecting with a [`WebError`].
+pub struct WebQuery<T>(pub T);
+#[async_trait]
+impl<S, T> FromRequestParts<S> for WebQuery<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = WebError;
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::from_request_parts(parts, state)
+            .await
+            .map_err(|err| WebError::Rejected(err.status(), err.body_text()))?;
+        Ok(Self(value))
+    }
+}
+/// A [`Form`] rejecting with a [`WebError`].
+pub struct WebForm<T>(pub T);
+#[async_trait]
+impl<S, B, T> FromRequest<S, B> for WebForm<T>
+where
+    S: Send + Sync,
+    B: axum::body::HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<axum::BoxError>,
+    T: DeserializeOwned,
+{
+    type Rejection = WebError;
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let Form(value) = Form::from_request(req, state)
+            .await
+            .map_err(|err| WebError::Rejected(err.status(), err.body_text()))?;
+        Ok(Self(value))
+    }
+}
+
+/// Respond to unknown paths.
+pub async fn fallback() -> WebError {
+    WebError::NotFound
+}
+
+#[derive(Debug, TemplateOnce)]
+#[template(path = "page/error.stpl")]
+pub struct ErrorPage {
+    pub title: String,
+    pub viewer: Viewer,
+    pub meta: PageMeta,
+    pub message: &'static str,
+    /// Why the request was rejected, if known.
+    pub detail: Option<String>,
+    /// Shown so that users can reference the request when asking for support.
+    pub request_id: RequestIdHeader,
+}
+impl ErrorPage {
+    fn new(
+        status: StatusCode,
+        detail: Option<String>,
+        viewer: Viewer,
+        request_id: RequestIdHeader,
+    ) -> Self {
+        let title = match status.canonical_reason() {
+            Some(reason) => format!("{} {reason}", status.as_u16()),
+            None => status.as_u16().to_string(),
+        };
+        let message = match status {
+            StatusCode::NOT_FOUND => "There's nothing here, it may have been moved or deleted.",
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                "You aren't allowed to view this page."
+            },
+            status if status.is_server_error() => {
+                "Something went wrong on our end. Please try again later."
+            },
+            _ => "The request couldn't be served.",
+        };
+        Self {
+            meta: PageMeta::new(title.as_str()),
+            title,
+            viewer,
+            message,
+            detail,
+            request_id,
+        }
+    }
+}
+
+/// Middleware rendering an [`ErrorPage`] for bodiless error responses, such as from a
+/// [`WebError`] or a bare [`StatusCode`], when the client asked for html.
+///
+/// Other clients, such as of the api or federation, keep the bare response.
+pub async fn error_pages<B>(req: Request<B>, next: Next<B>) -> Response {
+    let wants_html = accepts_html(req.headers());
+    let viewer = req
+        .extensions()
+        .get::<Viewer>()
+        .cloned()
+        .unwrap_or_default();
+    let request_id = req
+        .extensions()
+        .get::<RequestIdHeader>()
+        .cloned()
+        .unwrap_or_else(|| RequestId::default().into());
+    let res = next.run(req).await;
+    let status = res.status();
+    let bodiless = !res.headers().contains_key(header::CONTENT_TYPE);
+    if !wants_html || !bodiless || !(status.is_client_error() || status.is_server_error()) {
+        return res;
+    }
+    let detail = res
+        .extensions()
+        .get::<ErrorDetail>()
+        .map(|ErrorDetail(detail)| detail.clone());
+    let mut page = Template(ErrorPage::new(status, detail, viewer, request_id)).into_response();
+    // Rendering the page may itself fail, which still responds with the original status.
+    *page.status_mut() = status;
+    page
+}
+fn accepts_html(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|accept| accept.contains(mime::TEXT_HTML.essence_str()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::uuid::PostId;
+    use anyhow::anyhow;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower_service::Service;
+
+    async fn request(
+        path: &str,
+        accept: &str,
+        request_id: RequestId,
+    ) -> (StatusCode, Option<String>, String) {
+        let mut app = Router::new()
+            .route(
+                "/p/:id",
+                get(|WebPath(id): WebPath<PostId>| async move { id.to_string() }),
+            )
+            .route(
+                "/forbidden",
+                get(|| async { WebError::Forbidden("invalid csrf token") }),
+            )
+            .fallback(fallback)
+            .layer(middleware::from_fn(error_pages));
+        let mut req = Request::get(path)
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(RequestIdHeader::from(request_id));
+        let res = app.call(req).await.unwrap();
+        let content_type = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|value| value.to_str().unwrap().to_owned());
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (
+            status,
+            content_type,
+            String::from_utf8(body.to_vec()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn error_statuses() {
+        assert_eq!(WebError::NotFound.status(), StatusCode::NOT_FOUND);
+        let err = WebError::from(DbError::Other(anyhow!("db down")));
+        assert_eq!(err.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    #[tokio::test]
+    async fn html_error_pages() {
+        let html = "text/html,application/xhtml+xml;q=0.9";
+        let request_id = RequestId::new();
+        let (status, content_type, body) = request("/nowhere", html, request_id).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(content_type.unwrap().starts_with("text/html"));
+        assert!(body.contains(&request_id.to_string()), "{body}");
+        // Rejections by extractors, and the reasons of errors, are shown too.
+        let (status, content_type, body) = request("/p/garbage", html, request_id).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(content_type.unwrap().starts_with("text/html"));
+        assert!(body.contains("Invalid URL"), "{body}");
+        let (status, _, body) = request("/forbidden", html, request_id).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body.contains("invalid csrf token"), "{body}");
+        let id = PostId::new().to_string();
+        let (status, _, body) = request(&format!("/p/{id}"), html, request_id).await;
+        assert_eq!((status, body), (StatusCode::OK, id));
+    }
+    #[tokio::test]
+    async fn bare_errors_for_other_clients() {
+        for path in ["/nowhere", "/p/garbage", "/forbidden"] {
+            let (status, content_type, body) =
+                request(path, "application/json", RequestId::new()).await;
+            assert!(status.is_client_error(), "{path}");
+            assert_eq!((content_type, body), (None, String::new()), "{path}");
+        }
+    }
+}
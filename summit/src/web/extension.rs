@@ -1 +1,4 @@
+pub mod metrics;
 pub mod request_id;
+pub mod security_headers;
+pub mod viewer;
@@ -0,0 +1,59 @@
+use crate::Summit;
+use axum::{
+    extract::{MatchedPath, State},
+    http::{Method, Request},
+    middleware::Next,
+    response::Response,
+};
+use std::{sync::Arc, time::Instant};
+
+/// Middleware recording the latency and status of requests in
+/// [`crate::metrics::Metrics::http_requests`], by their route rather than their path, to bound
+/// the number of series.
+pub async fn track_requests<B>(
+    State(summit): State<Arc<Summit>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_owned(), |path| path.as_str().to_owned());
+    let method = method_label(req.method());
+    let start = Instant::now();
+    let res = next.run(req).await;
+    summit
+        .metrics()
+        .http_requests
+        .observe(&[&route, method, res.status().as_str()], start.elapsed());
+    res
+}
+/// The label of a request method, with non-standard methods as `other`, as any method can be
+/// sent.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::CONNECT => "CONNECT",
+        Method::OPTIONS => "OPTIONS",
+        Method::TRACE => "TRACE",
+        Method::PATCH => "PATCH",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn labels_only_standard_methods() {
+        assert_eq!(method_label(&Method::GET), "GET");
+        assert_eq!(method_label(&Method::PATCH), "PATCH");
+        let custom = Method::from_bytes(b"FOO-1234").unwrap();
+        assert_eq!(method_label(&custom), "other");
+    }
+}
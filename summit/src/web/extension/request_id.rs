@@ -1,48 +1,139 @@
 use crate::uuid::RequestId;
-use http::Request;
+use futures::ready;
+use http::{HeaderValue, Request, Response};
 use hyper::Body;
-use std::task::{Context, Poll};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
 use tower_layer::Layer;
 use tower_service::Service;
 use tracing::{error_span, Span};
 
+/// The header carrying the id of a request, from trusted proxies, back to clients, and along
+/// with outbound federation requests.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// The longest incoming id accepted from a trusted proxy.
+const MAX_INCOMING_ID_LEN: usize = 128;
+
+/// The id of a request as echoed to clients and traced, which is its [`RequestId`] unless a
+/// trusted proxy assigned another. Proxy ids needn't be a [`RequestId`], such as nginx's 32 hex
+/// digit `$request_id`.
+#[derive(Clone, Debug)]
+pub struct RequestIdHeader(HeaderValue);
+impl RequestIdHeader {
+    /// Accept an incoming id of bounded length and visible ascii, so it's safe to log and echo.
+    fn incoming(value: &HeaderValue) -> Option<Self> {
+        let bytes = value.as_bytes();
+        let valid = (1..=MAX_INCOMING_ID_LEN).contains(&bytes.len())
+            && bytes.iter().all(u8::is_ascii_graphic);
+        valid.then(|| Self(value.clone()))
+    }
+    pub fn as_str(&self) -> &str {
+        // Only constructed from visible ascii.
+        self.0.to_str().unwrap_or_default()
+    }
+}
+impl From<RequestId> for RequestIdHeader {
+    fn from(id: RequestId) -> Self {
+        Self(HeaderValue::from_str(&id.to_string()).expect("request ids are valid header values"))
+    }
+}
+impl fmt::Display for RequestIdHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Clone, Debug)]
-pub struct RequestIdService<S>(S);
-impl<B, S> Service<Request<B>> for RequestIdService<S>
+pub struct RequestIdService<S> {
+    inner: S,
+    trust_incoming: bool,
+}
+impl<B, ResB, S> Service<Request<B>> for RequestIdService<S>
 where
-    S: Service<Request<B>>,
+    S: Service<Request<B>, Response = Response<ResB>>,
 {
     type Response = S::Response;
     type Error = S::Error;
-    type Future = S::Future;
+    type Future = RequestIdFuture<S::Future>;
 
     #[inline]
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.0.poll_ready(cx)
+        self.inner.poll_ready(cx)
     }
 
     fn call(&mut self, mut req: Request<B>) -> Self::Future {
-        let id = RequestId::new();
+        let incoming = self
+            .trust_incoming
+            .then(|| req.headers().get(REQUEST_ID_HEADER))
+            .flatten()
+            .and_then(RequestIdHeader::incoming);
+        // Incoming ids which are themselves a `RequestId` are used as is, for correlating with
+        // other instances.
+        let id = incoming
+            .as_ref()
+            .and_then(|header| header.as_str().parse::<RequestId>().ok())
+            .unwrap_or_else(RequestId::new);
+        let header = incoming.unwrap_or_else(|| id.into());
         req.extensions_mut().insert(id);
-        self.0.call(req)
+        req.extensions_mut().insert(header.clone());
+        RequestIdFuture {
+            inner: self.inner.call(req),
+            header,
+        }
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct RequestIdLayer;
+pin_project! {
+    /// Echoes the request id in the response headers.
+    pub struct RequestIdFuture<F> {
+        #[pin]
+        inner: F,
+        header: RequestIdHeader,
+    }
+}
+impl<F, ResB, E> Future for RequestIdFuture<F>
+where
+    F: Future<Output = Result<Response<ResB>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut res = ready!(this.inner.poll(cx))?;
+        res.headers_mut()
+            .insert(REQUEST_ID_HEADER, this.header.0.clone());
+        Poll::Ready(Ok(res))
+    }
+}
+
+/// Assigns each request a [`RequestId`] and [`RequestIdHeader`] in its extensions, echoing the
+/// latter in the response headers.
+#[derive(Clone, Debug, Default)]
+pub struct RequestIdLayer {
+    /// Use the id of the incoming [`REQUEST_ID_HEADER`], if it's valid, rather than a new one.
+    pub trust_incoming: bool,
+}
 impl<S> Layer<S> for RequestIdLayer {
     type Service = RequestIdService<S>;
-    fn layer(&self, s: S) -> Self::Service {
-        RequestIdService(s)
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService {
+            inner,
+            trust_incoming: self.trust_incoming,
+        }
     }
 }
 
 pub fn trace_layer_span_with(request: &Request<Body>) -> Span {
     let id = request
         .extensions()
-        .get::<RequestId>()
-        .copied()
-        .unwrap_or_default();
+        .get::<RequestIdHeader>()
+        .cloned()
+        .unwrap_or_else(|| RequestId::default().into());
     error_span!(
         "request",
         %id,
@@ -50,3 +141,91 @@ pub fn trace_layer_span_with(request: &Request<Body>) -> Span {
         uri = %request.uri(),
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::Infallible;
+
+    /// Responds with the ids assigned to the request.
+    struct Echo;
+    impl Service<Request<()>> for Echo {
+        type Response = Response<Option<(RequestId, String)>>;
+        type Error = Infallible;
+        type Future = futures::future::Ready<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: Request<()>) -> Self::Future {
+            let ids = req.extensions().get::<RequestId>().copied().zip(
+                req.extensions()
+                    .get::<RequestIdHeader>()
+                    .map(ToString::to_string),
+            );
+            futures::future::ready(Ok(Response::new(ids)))
+        }
+    }
+    async fn request(trust_incoming: bool, incoming: Option<&str>) -> (RequestId, String, String) {
+        let mut req = Request::new(());
+        if let Some(incoming) = incoming {
+            req.headers_mut()
+                .insert(REQUEST_ID_HEADER, HeaderValue::from_str(incoming).unwrap());
+        }
+        let res = RequestIdLayer { trust_incoming }
+            .layer(Echo)
+            .call(req)
+            .await
+            .unwrap();
+        let echoed = res.headers()[REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let (id, header) = res.into_body().unwrap();
+        (id, header, echoed)
+    }
+
+    #[tokio::test]
+    async fn echoes_a_new_id() {
+        let (id, header, echoed) = request(false, None).await;
+        assert_eq!(header, id.to_string());
+        assert_eq!(echoed, header);
+    }
+    #[tokio::test]
+    async fn trusts_incoming_ids_only_when_configured() {
+        let nginx = "0123456789abcdef0123456789abcdef";
+        let (_, header, echoed) = request(true, Some(nginx)).await;
+        assert_eq!((header.as_str(), echoed.as_str()), (nginx, nginx));
+        let (id, header, echoed) = request(false, Some(nginx)).await;
+        assert_eq!(header, id.to_string());
+        assert_eq!(echoed, header);
+        // Our own ids keep their `RequestId`, such as from another instance.
+        let ours = RequestId::new();
+        let (id, _, echoed) = request(true, Some(&ours.to_string())).await;
+        assert_eq!(id, ours);
+        assert_eq!(echoed, ours.to_string());
+    }
+    #[tokio::test]
+    async fn replaces_garbage_incoming_ids() {
+        let too_long = "a".repeat(MAX_INCOMING_ID_LEN + 1);
+        for garbage in ["", "has space", "tab\there", too_long.as_str()] {
+            let (id, header, echoed) = request(true, Some(garbage)).await;
+            assert_eq!(header, id.to_string(), "{garbage:?}");
+            assert_eq!(echoed, header);
+        }
+        let mut req = Request::new(());
+        req.headers_mut().insert(
+            REQUEST_ID_HEADER,
+            HeaderValue::from_bytes("caf\u{e9}".as_bytes()).unwrap(),
+        );
+        let res = RequestIdLayer {
+            trust_incoming: true,
+        }
+        .layer(Echo)
+        .call(req)
+        .await
+        .unwrap();
+        let (id, header) = res.into_body().unwrap();
+        assert_eq!(header, id.to_string());
+    }
+}
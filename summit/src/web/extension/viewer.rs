@@ -0,0 +1,79 @@
+use crate::{
+    db::{LocalUser, Preferences},
+    uuid::UserId,
+    Summit,
+};
+use axum::{
+    extract::State, headers::Cookie, http::Request, middleware::Next, response::Response,
+    TypedHeader,
+};
+use std::sync::Arc;
+use tracing::error;
+
+/// The cookie naming the local user viewing pages, set by the dev login and only trusted in dev.
+//
+// TODO: Replace with real sessions, once users can log in. Anyone can claim to be anyone.
+pub const VIEWER_COOKIE: &str = "summit_viewer";
+
+/// The user viewing a page, their preferences, and the state shown to them on every page, such as
+/// the unread notification badge.
+#[derive(Debug, Default, Clone)]
+pub struct Viewer {
+    /// The viewing local user, or `None` if anonymous.
+    pub user: Option<LocalUser>,
+    /// The preferences of the user, or the defaults if anonymous.
+    pub prefs: Preferences,
+    pub unread_notifications: u64,
+}
+impl Viewer {
+    /// The id of the viewing user, with anonymous viewers sharing the default id.
+    pub fn user_id(&self) -> UserId {
+        self.user.as_ref().map(|user| user.id).unwrap_or_default()
+    }
+}
+
+/// Middleware resolving the [`Viewer`] of a request into its extensions.
+pub async fn resolve_viewer<B>(
+    State(summit): State<Arc<Summit>>,
+    cookie: Option<TypedHeader<Cookie>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let name = viewer_name(&cookie);
+    let res: crate::Result<_> = async {
+        let Some(name) = name else {
+            return Ok(Viewer::default());
+        };
+        let Some(user) = summit.local_user(name).await? else {
+            return Ok(Viewer::default());
+        };
+        let prefs = summit.preferences(user.id).await?;
+        let unread_notifications = summit.unread_notifications(user.id).await?;
+        Ok(Viewer {
+            user: Some(user),
+            prefs,
+            unread_notifications,
+        })
+    }
+    .await;
+    // Pages are still viewable anonymously, so don't fail the whole request.
+    let viewer = res.unwrap_or_else(|err| {
+        error!(?err, "failed to resolve viewer");
+        Viewer::default()
+    });
+    req.extensions_mut().insert(viewer);
+    next.run(req).await
+}
+
+/// The name of the local user in the [`VIEWER_COOKIE`].
+#[cfg(any(test, feature = "dev"))]
+fn viewer_name(cookie: &Option<TypedHeader<Cookie>>) -> Option<&str> {
+    cookie
+        .as_ref()
+        .and_then(|TypedHeader(cookie)| cookie.get(VIEWER_COOKIE))
+}
+/// The cookie is unsigned, so outside of dev every viewer is anonymous until real sessions exist.
+#[cfg(not(any(test, feature = "dev")))]
+fn viewer_name(_cookie: &Option<TypedHeader<Cookie>>) -> Option<&str> {
+    None
+}
@@ -1,4 +1,18 @@
+pub mod actor;
+pub mod admin;
+pub mod api;
 pub mod community;
 pub mod dev;
+pub mod feed;
+pub mod health;
+pub mod inbox;
 pub mod live;
+pub mod metrics;
+pub mod nodeinfo;
+pub mod notifications;
+pub mod post;
+pub mod preferences;
+pub mod profile;
 pub mod static_assets;
+pub mod tag;
+pub mod webfinger;
@@ -0,0 +1,118 @@
+use crate::{
+    db::{Actor, ActorKind},
+    web::handler::webfinger::ACTIVITY_JSON_CONTENT_TYPE,
+    Summit,
+};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::error;
+
+const CONTEXT: [&str; 2] = [
+    "https://www.w3.org/ns/activitystreams",
+    "https://w3id.org/security/v1",
+];
+
+/// An ActivityPub actor document, as fetched by remote servers resolving a local actor or its
+/// public key.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActorDoc {
+    #[serde(rename = "@context")]
+    pub context: [&'static str; 2],
+    pub id: String,
+    #[serde(rename = "type")]
+    pub type_: &'static str,
+    pub preferred_username: String,
+    pub inbox: String,
+    pub public_key: PublicKeyDoc,
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKeyDoc {
+    pub id: String,
+    pub owner: String,
+    pub public_key_pem: String,
+}
+
+/// Whether the request prefers an ActivityPub document over a html page, as sent by remote servers.
+pub fn wants_activity_json(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| {
+            let media_type = media_type.split(';').next().unwrap_or_default().trim();
+            media_type.eq_ignore_ascii_case("application/activity+json")
+                || media_type.eq_ignore_ascii_case("application/ld+json")
+        })
+}
+/// Mark a response as varying by `Accept`, for paths serving either a html page or, to
+/// [`wants_activity_json`] requests, an ActivityPub document.
+pub fn vary_accept(mut res: Response) -> Response {
+    res.headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    res
+}
+pub async fn user_handler(
+    State(summit): State<Arc<Summit>>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    handler(&summit, ActorKind::User, &name).await
+}
+pub async fn community_handler(
+    State(summit): State<Arc<Summit>>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    handler(&summit, ActorKind::Community, &name).await
+}
+async fn handler(
+    summit: &Summit,
+    kind: ActorKind,
+    name: &str,
+) -> Result<impl IntoResponse, StatusCode> {
+    let internal_error = |err| {
+        error!(?err, "failed to serve actor");
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let actor = summit
+        .local_actor(name)
+        .await
+        .map_err(internal_error)?
+        .filter(|actor: &Actor| actor.kind == kind)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let public_key_pem = summit
+        .actor_key(&actor)
+        .await
+        .map_err(internal_error)?
+        .public_key()
+        .to_pem()
+        .map_err(|err| internal_error(err.into()))?;
+    let fedi_config = summit.fedi_config();
+    let id = fedi_config.actor_url(&actor);
+    let doc = ActorDoc {
+        context: CONTEXT,
+        type_: match kind {
+            ActorKind::User => "Person",
+            ActorKind::Community => "Group",
+        },
+        preferred_username: actor.name.to_string(),
+        inbox: format!("{id}/inbox"),
+        public_key: PublicKeyDoc {
+            id: fedi_config.key_id(&actor),
+            owner: id.clone(),
+            public_key_pem,
+        },
+        id,
+    };
+    Ok((
+        [(header::CONTENT_TYPE, ACTIVITY_JSON_CONTENT_TYPE)],
+        Json(doc),
+    ))
+}
@@ -0,0 +1,42 @@
+use crate::{
+    db::{Delivery, DeliveryHost},
+    web::{
+        error::WebError,
+        extension::viewer::Viewer,
+        template::{meta::PageMeta, Template},
+    },
+    Summit,
+};
+use axum::extract::State;
+use sailfish::TemplateOnce;
+use std::sync::Arc;
+
+#[derive(Debug, TemplateOnce)]
+#[template(path = "page/admin_deliveries.stpl")]
+pub struct AdminDeliveries {
+    pub title: String,
+    pub viewer: Viewer,
+    pub meta: PageMeta,
+    pub pending: Vec<Delivery>,
+    pub failed: Vec<Delivery>,
+    pub dead_hosts: Vec<DeliveryHost>,
+}
+
+/// Served only on the admin listener, which has no viewers.
+//
+// TODO: Gate behind admin auth, once users can log in, and serve alongside the site.
+pub async fn handler(
+    State(summit): State<Arc<Summit>>,
+) -> Result<Template<AdminDeliveries>, WebError> {
+    let (pending, failed) = summit.delivery_queue().list(&summit).await?;
+    let mut dead_hosts = summit.delivery_hosts().await?;
+    dead_hosts.retain(|host| host.dead_since.is_some());
+    Ok(Template(AdminDeliveries {
+        title: "Deliveries".into(),
+        viewer: Viewer::default(),
+        meta: PageMeta::new("Deliveries"),
+        pending,
+        failed,
+        dead_hosts,
+    }))
+}
@@ -0,0 +1,217 @@
+//! The JSON API, for bots and clients, sharing [`Summit`] with the html handlers.
+use crate::{db::LocalUser, Summit};
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequest, FromRequestParts, Path, Query},
+    headers::{authorization::Bearer, Authorization},
+    http::{request::Parts, Request, StatusCode},
+    response::{IntoResponse, Response},
+    Json, RequestPartsExt, TypedHeader,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{borrow::Cow, sync::Arc};
+use tracing::error;
+
+pub mod v1;
+
+/// The default and maximum number of items per page.
+pub const DEFAULT_LIMIT: usize = 20;
+pub const MAX_LIMIT: usize = 100;
+
+/// An API error, responded as `{"error": {"code": .., "message": ..}}`.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    /// A stable, machine readable code such as `not_found`.
+    pub code: &'static str,
+    pub message: Cow<'static, str>,
+}
+impl ApiError {
+    pub fn new(
+        status: StatusCode,
+        code: &'static str,
+        message: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+    pub fn bad_request(message: impl Into<Cow<'static, str>>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+    pub fn not_found(message: impl Into<Cow<'static, str>>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+    pub fn unauthorized() -> Self {
+        Self::new(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "a valid bearer token is required",
+        )
+    }
+    /// Log the error, responding without its details.
+    pub fn internal(err: crate::Error) -> Self {
+        error!(?err, "api request failed");
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal",
+            "internal server error",
+        )
+    }
+}
+impl From<crate::Error> for ApiError {
+    fn from(err: crate::Error) -> Self {
+        Self::internal(err)
+    }
+}
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        #[derive(Serialize)]
+        struct Body<'a> {
+            error: ErrorBody<'a>,
+        }
+        #[derive(Serialize)]
+        struct ErrorBody<'a> {
+            code: &'a str,
+            message: &'a str,
+        }
+        let body = Body {
+            error: ErrorBody {
+                code: self.code,
+                message: &self.message,
+            },
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// A [`Path`] rejecting with an [`ApiError`].
+pub struct ApiPath<T>(pub T);
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ApiPath<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + Send,
+{
+    type Rejection = ApiError;
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::from_request_parts(parts, state)
+            .await
+            .map_err(|err| ApiError::bad_request(err.body_text()))?;
+        Ok(Self(value))
+    }
+}
+/// A [`Query`] rejecting with an [`ApiError`].
+pub struct ApiQuery<T>(pub T);
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ApiQuery<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = ApiError;
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::from_request_parts(parts, state)
+            .await
+            .map_err(|err| ApiError::bad_request(err.body_text()))?;
+        Ok(Self(value))
+    }
+}
+/// A [`Json`] body rejecting with an [`ApiError`].
+pub struct ApiJson<T>(pub T);
+#[async_trait]
+impl<S, B, T> FromRequest<S, B> for ApiJson<T>
+where
+    S: Send + Sync,
+    B: axum::body::HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<axum::BoxError>,
+    T: DeserializeOwned,
+{
+    type Rejection = ApiError;
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::from_request(req, state)
+            .await
+            .map_err(|err| ApiError::new(err.status(), "invalid_body", err.body_text()))?;
+        Ok(Self(value))
+    }
+}
+
+/// The local user authenticated by the `Authorization: Bearer` token of a request.
+pub struct ApiUser(pub LocalUser);
+#[async_trait]
+impl FromRequestParts<Arc<Summit>> for ApiUser {
+    type Rejection = ApiError;
+    async fn from_request_parts(
+        parts: &mut Parts,
+        summit: &Arc<Summit>,
+    ) -> Result<Self, Self::Rejection> {
+        let TypedHeader(Authorization(bearer)) = parts
+            .extract::<TypedHeader<Authorization<Bearer>>>()
+            .await
+            .map_err(|_| ApiError::unauthorized())?;
+        let user = summit
+            .api_token_user(bearer.token())
+            .await?
+            .ok_or_else(ApiError::unauthorized)?;
+        Ok(Self(user))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PageQuery<C> {
+    /// The `next_cursor` of the previous page, or none for the first page.
+    pub cursor: Option<C>,
+    pub limit: Option<usize>,
+}
+impl<C> PageQuery<C> {
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+}
+/// A page of items, with the cursor of the next page if there may be more.
+#[derive(Debug, Serialize)]
+pub struct Page<T, C> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<C>,
+}
+impl<T, C> Page<T, C> {
+    /// A page from items fetched with one more than the page limit, to know whether there's a
+    /// next page. If so, the extra item is dropped and the cursor taken from the last item kept.
+    pub fn new(mut items: Vec<T>, limit: usize, cursor: impl Fn(&T) -> C) -> Self {
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(cursor)
+        } else {
+            None
+        };
+        Self { items, next_cursor }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn page_cursor_only_when_more() {
+        let page = Page::new(vec![1, 2, 3, 4], 3, |&n| n);
+        assert_eq!((page.items, page.next_cursor), (vec![1, 2, 3], Some(3)));
+        let page = Page::new(vec![1, 2, 3], 3, |&n| n);
+        assert_eq!((page.items, page.next_cursor), (vec![1, 2, 3], None));
+        let page = Page::new(vec![1, 2], 3, |&n| n);
+        assert_eq!(page.next_cursor, None);
+    }
+    #[tokio::test]
+    async fn error_body() {
+        let res = ApiError::not_found("no such post").into_response();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(
+            &body[..],
+            br#"{"error":{"code":"not_found","message":"no such post"}}"#
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{web::handler::api::ApiError, Summit};
+use axum::{
+    routing::{get, post},
+    Router,
+};
+use std::sync::Arc;
+
+pub mod communities;
+pub mod notifications;
+pub mod posts;
+pub mod users;
+
+/// The routes of `/api/v1`.
+pub fn router() -> Router<Arc<Summit>> {
+    Router::new()
+        .route("/posts", get(posts::list).post(posts::create))
+        .route("/posts/:id", get(posts::get))
+        .route("/communities/:name", get(communities::get))
+        .route("/users/:addr", get(users::get))
+        .route("/users/:addr/posts", get(users::posts))
+        .route("/notifications", get(notifications::list))
+        .route("/notifications/read", post(notifications::read))
+        .fallback(|| async { ApiError::not_found("no such endpoint") })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        db::{Author, CreatePost, FediAddr},
+        dev::db::DevDb,
+        SummitConfig,
+    };
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+    };
+    use serde_json::{json, Value};
+    use tower_service::Service;
+
+    async fn summit() -> Arc<Summit> {
+        let summit = Arc::new(Summit::new(
+            SummitConfig::default(),
+            Box::<DevDb>::default(),
+        ));
+        let host = summit.fedi_config().host().to_owned();
+        for body in ["one", "two", "three"] {
+            let author = Author {
+                fedi_addr: FediAddr {
+                    user: "alice".into(),
+                    host: host.as_str().into(),
+                },
+            };
+            summit
+                .create_post(CreatePost {
+                    author,
+                    title: "Title".into(),
+                    body: body.into(),
+                })
+                .await
+                .unwrap();
+        }
+        summit
+    }
+    async fn request(summit: &Arc<Summit>, req: Request<Body>) -> (StatusCode, Value) {
+        let res = router()
+            .with_state(Arc::clone(summit))
+            .call(req)
+            .await
+            .unwrap();
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+    fn error_code(body: &Value) -> &str {
+        body["error"]["code"].as_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn bearer_auth() {
+        let summit = summit().await;
+        let (status, body) = request(&summit, get("/notifications")).await;
+        assert_eq!(
+            (status, error_code(&body)),
+            (StatusCode::UNAUTHORIZED, "unauthorized")
+        );
+        let req = Request::get("/notifications")
+            .header(header::AUTHORIZATION, "Bearer not-a-token")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = request(&summit, req).await;
+        assert_eq!(
+            (status, error_code(&body)),
+            (StatusCode::UNAUTHORIZED, "unauthorized")
+        );
+        let alice = summit.local_user("alice").await.unwrap().unwrap();
+        let token = summit.create_api_token(alice.id).await.unwrap();
+        let req = Request::get("/notifications")
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = request(&summit, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "items": [], "next_cursor": null }));
+    }
+    #[tokio::test]
+    async fn rejections_are_api_errors() {
+        let summit = summit().await;
+        let (status, body) = request(&summit, get("/posts?limit=many")).await;
+        assert_eq!(
+            (status, error_code(&body)),
+            (StatusCode::BAD_REQUEST, "bad_request")
+        );
+        let (status, body) = request(&summit, get("/posts/garbage")).await;
+        assert_eq!(
+            (status, error_code(&body)),
+            (StatusCode::BAD_REQUEST, "bad_request")
+        );
+        let (status, body) = request(&summit, get("/users/not@an@addr")).await;
+        assert_eq!(
+            (status, error_code(&body)),
+            (StatusCode::BAD_REQUEST, "bad_request")
+        );
+        let alice = summit.local_user("alice").await.unwrap().unwrap();
+        let token = summit.create_api_token(alice.id).await.unwrap();
+        let post = |content_type: &str, body: &str| {
+            Request::post("/posts")
+                .header(header::AUTHORIZATION, format!("Bearer {token}"))
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body.to_owned()))
+                .unwrap()
+        };
+        let (status, body) = request(&summit, post("application/json", "{")).await;
+        assert_eq!(
+            (status, error_code(&body)),
+            (StatusCode::BAD_REQUEST, "invalid_body")
+        );
+        let (status, body) = request(&summit, post("text/plain", "{}")).await;
+        assert_eq!(
+            (status, error_code(&body)),
+            (StatusCode::UNSUPPORTED_MEDIA_TYPE, "invalid_body")
+        );
+    }
+    #[tokio::test]
+    async fn fallback() {
+        let summit = summit().await;
+        let (status, body) = request(&summit, get("/nowhere")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            json!({ "error": { "code": "not_found", "message": "no such endpoint" } })
+        );
+    }
+    #[tokio::test]
+    async fn pages_follow_the_cursor() {
+        let summit = summit().await;
+        let (status, body) = request(&summit, get("/users/alice/posts?limit=2")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["items"].as_array().unwrap().len(), 2);
+        let cursor = body["next_cursor"].as_str().unwrap();
+        let uri = format!("/users/alice/posts?limit=2&cursor={cursor}");
+        let (_, body) = request(&summit, get(&uri)).await;
+        assert_eq!(body["items"].as_array().unwrap().len(), 1);
+        assert_eq!(body["next_cursor"], Value::Null);
+        // A full last page has no next page either.
+        let (_, body) = request(&summit, get("/posts?limit=3")).await;
+        assert_eq!(body["items"].as_array().unwrap().len(), 3);
+        assert_eq!(body["next_cursor"], Value::Null);
+    }
+}
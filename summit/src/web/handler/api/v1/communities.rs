@@ -0,0 +1,18 @@
+use crate::{
+    db::Community,
+    web::handler::api::{ApiError, ApiPath},
+    Summit,
+};
+use axum::{extract::State, Json};
+use std::sync::Arc;
+
+pub async fn get(
+    State(summit): State<Arc<Summit>>,
+    ApiPath(name): ApiPath<String>,
+) -> Result<Json<Community>, ApiError> {
+    let community = summit
+        .community(&name)
+        .await?
+        .ok_or_else(|| ApiError::not_found("no such community"))?;
+    Ok(Json(community))
+}
@@ -0,0 +1,38 @@
+use crate::{
+    db::Notification,
+    uuid::NotificationId,
+    web::handler::api::{ApiError, ApiJson, ApiQuery, ApiUser, Page, PageQuery},
+    Summit,
+};
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct MarkRead {
+    /// The notifications to mark as read, or all of them if omitted.
+    pub ids: Option<Vec<NotificationId>>,
+}
+
+/// Notifications of the authenticated user, newest first.
+pub async fn list(
+    State(summit): State<Arc<Summit>>,
+    ApiUser(user): ApiUser,
+    ApiQuery(query): ApiQuery<PageQuery<NotificationId>>,
+) -> Result<Json<Page<Notification, NotificationId>>, ApiError> {
+    let limit = query.limit();
+    let notifications = summit
+        .notifications(user.id, query.cursor, limit + 1)
+        .await?;
+    Ok(Json(Page::new(notifications, limit, |n| n.id)))
+}
+pub async fn read(
+    State(summit): State<Arc<Summit>>,
+    ApiUser(user): ApiUser,
+    ApiJson(mark_read): ApiJson<MarkRead>,
+) -> Result<StatusCode, ApiError> {
+    summit
+        .mark_notifications_read(user.id, mark_read.ids.as_deref())
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,64 @@
+use crate::{
+    db::{Author, CreatePost, FediAddr, Post},
+    uuid::PostId,
+    web::handler::api::{ApiError, ApiJson, ApiPath, ApiQuery, ApiUser, Page, PageQuery},
+    Summit,
+};
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct NewPost {
+    pub title: String,
+    /// Markdown.
+    pub body: String,
+}
+
+/// The latest posts, newest first.
+pub async fn list(
+    State(summit): State<Arc<Summit>>,
+    ApiQuery(query): ApiQuery<PageQuery<PostId>>,
+) -> Result<Json<Page<Post, PostId>>, ApiError> {
+    let limit = query.limit();
+    let posts = summit.posts(query.cursor, limit + 1).await?;
+    Ok(Json(Page::new(posts, limit, |post| post.id)))
+}
+pub async fn get(
+    State(summit): State<Arc<Summit>>,
+    ApiPath(id): ApiPath<PostId>,
+) -> Result<Json<Post>, ApiError> {
+    let post = summit
+        .post(id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("no such post"))?;
+    Ok(Json(post))
+}
+/// Create a post authored by the authenticated user.
+pub async fn create(
+    State(summit): State<Arc<Summit>>,
+    ApiUser(user): ApiUser,
+    ApiJson(new_post): ApiJson<NewPost>,
+) -> Result<(StatusCode, Json<Post>), ApiError> {
+    let NewPost { title, body } = new_post;
+    if title.trim().is_empty() {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_post",
+            "title must not be empty",
+        ));
+    }
+    let post = summit
+        .create_post(CreatePost {
+            author: Author {
+                fedi_addr: FediAddr {
+                    user: user.name,
+                    host: summit.fedi_config().host().into(),
+                },
+            },
+            title,
+            body,
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(post)))
+}
@@ -0,0 +1,37 @@
+use crate::{
+    db::{FediAddr, Post, Profile},
+    uuid::PostId,
+    web::handler::api::{ApiError, ApiPath, ApiQuery, Page, PageQuery},
+    Summit,
+};
+use axum::{extract::State, Json};
+use std::sync::Arc;
+
+/// Parse a local `user`, or any `user@host`.
+fn parse_addr(summit: &Summit, addr: &str) -> Result<FediAddr, ApiError> {
+    FediAddr::parse_or_local(addr, summit.fedi_config().host())
+        .map_err(|err| ApiError::bad_request(format!("invalid address: {err}")))
+}
+
+pub async fn get(
+    State(summit): State<Arc<Summit>>,
+    ApiPath(addr): ApiPath<String>,
+) -> Result<Json<Profile>, ApiError> {
+    let addr = parse_addr(&summit, &addr)?;
+    let profile = summit
+        .profile(&addr)
+        .await?
+        .ok_or_else(|| ApiError::not_found("no such user"))?;
+    Ok(Json(profile))
+}
+/// Posts by a user, newest first.
+pub async fn posts(
+    State(summit): State<Arc<Summit>>,
+    ApiPath(addr): ApiPath<String>,
+    ApiQuery(query): ApiQuery<PageQuery<PostId>>,
+) -> Result<Json<Page<Post, PostId>>, ApiError> {
+    let addr = parse_addr(&summit, &addr)?;
+    let limit = query.limit();
+    let posts = summit.user_posts(&addr, query.cursor, limit + 1).await?;
+    Ok(Json(Page::new(posts, limit, |post| post.id)))
+}
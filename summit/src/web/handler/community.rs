@@ -1,10 +1,22 @@
 use crate::{
-    date_time::{DateTime, TimeZone},
+    date_time::{DateTime, DateTimePrefs},
     db::{Author, Post},
-    web::template::{MarkdownHtml, Template},
+    uuid::PostId,
+    web::{
+        error::{WebError, WebPath},
+        extension::viewer::Viewer,
+        handler::{actor, feed::FeedLink},
+        template::{meta::PageMeta, MarkdownHtml, Template},
+    },
     Summit,
 };
-use axum::extract::State;
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use compact_str::CompactString;
 use sailfish::TemplateOnce;
 use std::sync::Arc;
 use tracing::info;
@@ -14,27 +26,35 @@ use tracing::info;
 pub struct Community<P: Iterator<Item = CommunityPost>> {
     // pub user: NotLoggedIn,
     pub title: String,
+    pub viewer: Viewer,
+    pub meta: PageMeta,
+    /// The name of the community, if any, whose time zone live posts are shown in.
+    pub community: Option<CompactString>,
     pub posts: P,
 }
 #[derive(Debug, TemplateOnce)]
 #[template(path = "component/community_post.stpl")]
 pub struct CommunityPost {
-    pub user_tz: TimeZone,
+    pub id: PostId,
+    pub date_prefs: DateTimePrefs,
     pub author: Author,
     pub created_on: DateTime,
     pub title_html: MarkdownHtml,
     pub body_html: MarkdownHtml,
 }
 impl CommunityPost {
-    pub fn new(user_tz: TimeZone, post: Post) -> Self {
+    pub fn new(date_prefs: DateTimePrefs, post: Post) -> Self {
         let Post {
+            id,
             author,
             created_on,
             title,
             body,
+            ..
         } = post;
         Self {
-            user_tz,
+            id,
+            date_prefs,
             author,
             created_on,
             title_html: title.into(),
@@ -45,15 +65,63 @@ impl CommunityPost {
 
 pub async fn handler(
     State(summit): State<Arc<Summit>>,
-) -> Template<Community<impl Iterator<Item = CommunityPost>>> {
+    Extension(viewer): Extension<Viewer>,
+) -> Result<Template<Community<impl Iterator<Item = CommunityPost>>>, WebError> {
     info!("community");
 
-    let user_tz = TimeZone::default();
-    let posts = summit.posts().await.unwrap();
-    Template(Community {
+    let date_prefs = viewer.prefs.date_time();
+    let posts = summit.posts(None, viewer.prefs.posts_per_page()).await?;
+    Ok(Template(Community {
         title: "Some Title".into(),
+        viewer,
+        meta: PageMeta::new("Some Title").with_postings(summit.fedi_config(), &posts),
+        community: None,
         posts: posts
             .into_iter()
-            .map(move |post| CommunityPost::new(user_tz, post)),
-    })
+            .map(move |post| CommunityPost::new(date_prefs, post)),
+    }))
+}
+/// Serve the page of a local community, shown in the community's time zone unless the viewer
+/// chose their own.
+///
+/// ActivityPub requests are served the actor document instead.
+//
+// TODO: Only list posts of this community, once posts belong to one.
+pub async fn community_handler(
+    State(summit): State<Arc<Summit>>,
+    WebPath(name): WebPath<String>,
+    Extension(viewer): Extension<Viewer>,
+    headers: HeaderMap,
+) -> Result<Response, WebError> {
+    if actor::wants_activity_json(&headers) {
+        return Ok(actor::vary_accept(
+            actor::community_handler(State(summit), Path(name))
+                .await
+                .into_response(),
+        ));
+    }
+    let community = summit.community(&name).await?.ok_or(WebError::NotFound)?;
+    let date_prefs = viewer
+        .prefs
+        .date_time_in(community.time_zone.unwrap_or_default());
+    let posts = summit.posts(None, viewer.prefs.posts_per_page()).await?;
+    let meta = PageMeta {
+        url: Some(summit.fedi_config().community_url(&community.name)),
+        description: Some(format!("Posts of the {} community", community.name)),
+        feed: Some(FeedLink::community(&community.name)),
+        ..PageMeta::new(community.name.as_str())
+    }
+    .with_postings(summit.fedi_config(), &posts);
+    Ok(actor::vary_accept(
+        Template(Community {
+            title: community.name.to_string(),
+            viewer,
+            meta,
+            community: Some(community.name),
+            posts: posts
+                .into_iter()
+                .map(move |post| CommunityPost::new(date_prefs, post)),
+        })
+        .into_response(),
+    ))
 }
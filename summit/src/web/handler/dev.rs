@@ -1 +1,5 @@
+pub mod login;
+#[cfg(feature = "local_dev")]
+pub mod reload;
+#[cfg(feature = "local_dev")]
 pub mod restart;
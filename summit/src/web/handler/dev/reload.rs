@@ -0,0 +1,264 @@
+//! Reloading of static assets and templates in `local_dev`.
+//!
+//! Assets are served from disk rather than the binary, and a background task watches `static/`,
+//! pushing a reload to pages over `/dev/watch-restart` whenever it changes, without restarting.
+//!
+//! Templates are compiled into the binary by sailfish, so they can't be reloaded in place.
+//! Instead the same task watches `templates/`, rebuilds the server as they change, and replaces
+//! itself with the new binary. Pages reload as `dev_restart.js` reconnects, and a failed build
+//! leaves the running server be. So only Rust sources need an outside restarting watcher, which
+//! should ignore both, eg `cargo watch -i summit/static -i templates ..`.
+use crate::web::{error::WebError, handler::static_assets, shutdown::ShutdownSignal};
+use anyhow::{anyhow, bail};
+use axum::{
+    extract::Path,
+    headers::HeaderMapExt,
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::{
+    fs, io,
+    path::{Path as FsPath, PathBuf},
+    pin::pin,
+    process::{Command, Stdio},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::watch;
+use tracing::{debug, error, info};
+
+/// How often the watched directories are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The directories assets are read from, in order.
+fn asset_dirs() -> [&'static FsPath; 2] {
+    [
+        FsPath::new(env!("STATIC_DIR")),
+        FsPath::new(env!("VENDOR_DIR")),
+    ]
+}
+
+fn templates_dir() -> PathBuf {
+    FsPath::new(env!("CARGO_MANIFEST_DIR")).join("../templates")
+}
+
+/// A notification of changed assets, for pages to reload.
+#[derive(Debug, Clone)]
+pub struct DevReload(watch::Receiver<u64>);
+impl DevReload {
+    /// Start a background task watching the asset and template directories, until shutdown.
+    pub fn watch(shutdown_signal: ShutdownSignal) -> Self {
+        let (sender, receiver) = watch::channel(0);
+        #[allow(clippy::let_underscore_future)]
+        let _ = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            let mut assets = Snapshot::default();
+            let mut templates = Snapshot::default();
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {},
+                    _ = &mut pin!(shutdown_signal.recv()) => return,
+                }
+                let res = tokio::task::spawn_blocking(|| {
+                    (
+                        Snapshot::of(&asset_dirs()),
+                        Snapshot::of(&[&templates_dir()]),
+                    )
+                })
+                .await;
+                let (next_assets, next_templates) = match res {
+                    Ok(snapshots) => snapshots,
+                    Err(err) => {
+                        error!(?err, "failed to watch dev assets");
+                        continue;
+                    },
+                };
+                if assets.changed_to(&next_assets) {
+                    info!("static assets changed, reloading pages");
+                    sender.send_modify(|generation| *generation += 1);
+                }
+                if templates.changed_to(&next_templates) {
+                    info!("templates changed, rebuilding..");
+                    match tokio::task::spawn_blocking(rebuild).await {
+                        Ok(Ok(exe)) => {
+                            info!(?exe, "rebuilt, restarting");
+                            let err = restart(&exe);
+                            error!(?err, ?exe, "failed to restart after rebuilding");
+                        },
+                        Ok(Err(err)) => error!(?err, "failed to rebuild for changed templates"),
+                        Err(err) => error!(?err, "failed to rebuild for changed templates"),
+                    }
+                }
+                (assets, templates) = (next_assets, next_templates);
+            }
+        });
+        Self(receiver)
+    }
+    /// Only wait for changes from now on. Clones of the receiver in the router state would
+    /// otherwise see every change since the watch started, and reload pages as soon as they
+    /// connect.
+    pub fn mark_seen(&mut self) {
+        self.0.borrow_and_update();
+    }
+    /// Wait for the next change of assets.
+    pub async fn changed(&mut self) {
+        if self.0.changed().await.is_err() {
+            // The watcher stopped, such as for shutdown, so there's nothing to wait for.
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Build the server binary with the features and profile of this one, returning its path.
+///
+/// Compiler output is shown as usual, with only the build messages captured.
+fn rebuild() -> anyhow::Result<PathBuf> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut features = vec!["local_dev"];
+    if cfg!(feature = "simd") {
+        features.push("simd");
+    }
+    let mut command = Command::new(cargo);
+    command
+        .args([
+            "build",
+            "--bin",
+            "summit",
+            "--message-format=json-render-diagnostics",
+        ])
+        .arg("--manifest-path")
+        .arg(FsPath::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+        .arg("--features")
+        .arg(features.join(","))
+        .stdout(Stdio::piped());
+    if !cfg!(debug_assertions) {
+        command.arg("--release");
+    }
+    let output = command.output()?;
+    if !output.status.success() {
+        bail!("build failed with {}", output.status);
+    }
+    let messages = String::from_utf8_lossy(&output.stdout);
+    executable_of(&messages).ok_or_else(|| anyhow!("build produced no summit executable"))
+}
+/// The path of the `summit` executable in the JSON messages of a build.
+fn executable_of(messages: &str) -> Option<PathBuf> {
+    #[derive(Deserialize)]
+    struct Message {
+        reason: String,
+        target: Option<Target>,
+        executable: Option<PathBuf>,
+    }
+    #[derive(Deserialize)]
+    struct Target {
+        name: String,
+    }
+    messages
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Message>(line).ok())
+        .filter(|message| message.reason == "compiler-artifact")
+        .filter(|message| {
+            message
+                .target
+                .as_ref()
+                .map_or(false, |t| t.name == "summit")
+        })
+        .find_map(|message| message.executable)
+}
+/// Replace this process with the given executable, with the same arguments. Only returns on
+/// failure.
+#[cfg(unix)]
+fn restart(exe: &FsPath) -> io::Error {
+    use std::os::unix::process::CommandExt;
+    Command::new(exe).args(std::env::args_os().skip(1)).exec()
+}
+#[cfg(not(unix))]
+fn restart(_exe: &FsPath) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "restarting in place is only supported on unix, restart to see the changes",
+    )
+}
+
+/// The modification times of every file in some directories.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Snapshot(Option<Vec<(PathBuf, SystemTime)>>);
+impl Snapshot {
+    fn of(dirs: &[&FsPath]) -> Self {
+        let mut files = Vec::new();
+        for dir in dirs {
+            list_files(dir, &mut files);
+        }
+        files.sort();
+        Self(Some(files))
+    }
+    /// Whether the files changed since this snapshot, ignoring the first.
+    fn changed_to(&self, next: &Self) -> bool {
+        self.0.is_some() && self != next
+    }
+}
+fn list_files(dir: &FsPath, files: &mut Vec<(PathBuf, SystemTime)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            list_files(&path, files);
+        } else if let Ok(modified) = metadata.modified() {
+            files.push((path, modified));
+        }
+    }
+}
+
+/// Serve assets from disk, so that edits show without a rebuild. Only listed assets are served,
+/// by either their plain or hashed path.
+pub async fn serve_asset(Path(asset_path): Path<String>) -> Response {
+    debug!(asset_path, "serving asset from disk");
+    let Some((path, content_type)) = static_assets::asset_source(&asset_path) else {
+        return WebError::NotFound.into_response();
+    };
+    for dir in asset_dirs() {
+        match tokio::fs::read(dir.join(path)).await {
+            Ok(bytes) => {
+                let mut res = bytes.into_response();
+                res.headers_mut().typed_insert(content_type);
+                res.headers_mut()
+                    .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+                return res;
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                error!(?err, path, "failed to read asset");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            },
+        }
+    }
+    error!(path, "listed asset missing from disk");
+    WebError::NotFound.into_response()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_the_built_executable() {
+        let messages = r#"
+{"reason":"compiler-artifact","target":{"name":"summit","kind":["lib"]},"executable":null}
+{"reason":"compiler-artifact","target":{"name":"summit","kind":["bin"]},"executable":"/t/debug/summit"}
+{"reason":"build-finished","success":true}
+"#;
+        assert_eq!(
+            executable_of(messages),
+            Some(PathBuf::from("/t/debug/summit"))
+        );
+        assert_eq!(
+            executable_of(r#"{"reason":"build-finished","success":false}"#),
+            None
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use crate::web::{handler::live::ConnectionGuard, shutdown::ShutdownSignal};
+use crate::web::{
+    handler::{dev::reload::DevReload, live::ConnectionGuard},
+    shutdown::ShutdownSignal,
+};
 use axum::{
     extract::State,
     response::sse::{Event, Sse},
@@ -7,10 +10,13 @@ use futures::stream::Stream;
 use std::{convert::Infallible, pin::pin, time::Duration};
 use tracing::{trace, Span};
 
+/// Held open by `dev_restart.js`, which reloads the page once it reconnects after a restart, such
+/// as once changed templates are rebuilt, or when sent a `reload` event as static assets change.
 pub async fn restart_handler(
-    State(shutdown_signal): State<ShutdownSignal>,
+    State((shutdown_signal, mut dev_reload)): State<(ShutdownSignal, DevReload)>,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     trace!("opening dev restart sse connection");
+    dev_reload.mark_seen();
 
     let stream = async_stream::stream! {
         let mut interval = tokio::time::interval(Duration::from_secs(60));
@@ -23,6 +29,9 @@ pub async fn restart_handler(
                 _ = interval.tick() => {
                     yield Ok(Event::default().event("restart heartbeat"));
                 }
+                _ = &mut pin!(dev_reload.changed()) => {
+                    yield Ok(Event::default().event("reload").data(""));
+                }
                 _ = &mut pin!(shutdown_signal.recv()) => {
                     conn_guard.closed_via_shutdown_signal = true;
                     return;
@@ -0,0 +1,510 @@
+//! Atom and RSS feeds of communities, users and tags, for following them in feed readers.
+//!
+//! The xml is written directly rather than through sailfish, as release builds minify templates
+//! as html, which would mangle elements such as the RSS `<link>`.
+use crate::{
+    date_time::DateTime,
+    db::Post,
+    fedi::FediConfig,
+    web::{
+        error::{WebError, WebPath},
+        handler::profile::profile_addr,
+        template::MarkdownHtml,
+    },
+    Summit,
+};
+use anyhow::anyhow;
+use axum::{
+    extract::State,
+    headers::{ContentType, ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use data_encoding::BASE64URL_NOPAD;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::{self, Write},
+    sync::Arc,
+    time::SystemTime,
+};
+
+/// The number of posts listed in a feed.
+const FEED_LEN: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+}
+impl FeedFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Atom => "application/atom+xml; charset=utf-8",
+            Self::Rss => "application/rss+xml; charset=utf-8",
+        }
+    }
+    /// The file name of this feed, relative to the page it's a feed of.
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Self::Atom => "feed.atom",
+            Self::Rss => "feed.rss",
+        }
+    }
+}
+
+/// A link to the feeds of a page, advertised with `<link rel="alternate">` in the page head.
+#[derive(Debug, Clone)]
+pub struct FeedLink {
+    pub title: String,
+    /// The path of the page, which the feed file names are relative to.
+    pub path: String,
+}
+impl FeedLink {
+    pub fn community(name: &str) -> Self {
+        Self {
+            title: name.to_owned(),
+            path: format!("/c/{name}"),
+        }
+    }
+    pub fn user(name: &str) -> Self {
+        Self {
+            title: name.to_owned(),
+            path: format!("/u/{name}"),
+        }
+    }
+    pub fn tag(tag: &str) -> Self {
+        Self {
+            title: format!("#{tag}"),
+            path: format!("/t/{tag}"),
+        }
+    }
+    pub fn atom_href(&self) -> String {
+        format!("{}/{}", self.path, FeedFormat::Atom.file_name())
+    }
+    pub fn rss_href(&self) -> String {
+        format!("{}/{}", self.path, FeedFormat::Rss.file_name())
+    }
+}
+
+pub async fn community_atom(
+    State(summit): State<Arc<Summit>>,
+    WebPath(name): WebPath<String>,
+    headers: HeaderMap,
+) -> Result<Response, WebError> {
+    community_feed(&summit, &name, FeedFormat::Atom, &headers).await
+}
+pub async fn community_rss(
+    State(summit): State<Arc<Summit>>,
+    WebPath(name): WebPath<String>,
+    headers: HeaderMap,
+) -> Result<Response, WebError> {
+    community_feed(&summit, &name, FeedFormat::Rss, &headers).await
+}
+// TODO: Only list posts of this community, once posts belong to one.
+async fn community_feed(
+    summit: &Summit,
+    name: &str,
+    format: FeedFormat,
+    headers: &HeaderMap,
+) -> Result<Response, WebError> {
+    let community = summit.community(name).await?.ok_or(WebError::NotFound)?;
+    let posts = summit.posts(None, FEED_LEN).await?;
+    let feed = Feed {
+        config: summit.fedi_config(),
+        title: community.name.to_string(),
+        page_url: summit.fedi_config().community_url(&community.name),
+        posts,
+    };
+    feed.respond(format, headers)
+}
+
+pub async fn user_atom(
+    State(summit): State<Arc<Summit>>,
+    WebPath(name): WebPath<String>,
+    headers: HeaderMap,
+) -> Result<Response, WebError> {
+    user_feed(&summit, &name, FeedFormat::Atom, &headers).await
+}
+pub async fn user_rss(
+    State(summit): State<Arc<Summit>>,
+    WebPath(name): WebPath<String>,
+    headers: HeaderMap,
+) -> Result<Response, WebError> {
+    user_feed(&summit, &name, FeedFormat::Rss, &headers).await
+}
+async fn user_feed(
+    summit: &Summit,
+    name: &str,
+    format: FeedFormat,
+    headers: &HeaderMap,
+) -> Result<Response, WebError> {
+    let addr = profile_addr(summit, name)?;
+    let profile = summit.profile(&addr).await?.ok_or(WebError::NotFound)?;
+    let posts = summit.user_posts(&addr, None, FEED_LEN).await?;
+    let feed = Feed {
+        config: summit.fedi_config(),
+        title: profile
+            .display_name
+            .unwrap_or_else(|| profile.fedi_addr.to_string()),
+        page_url: summit.fedi_config().url(&format!(
+            "/u/{}@{}",
+            profile.fedi_addr.user, profile.fedi_addr.host
+        )),
+        posts,
+    };
+    feed.respond(format, headers)
+}
+
+pub async fn tag_atom(
+    State(summit): State<Arc<Summit>>,
+    WebPath(tag): WebPath<String>,
+    headers: HeaderMap,
+) -> Result<Response, WebError> {
+    tag_feed(&summit, &tag, FeedFormat::Atom, &headers).await
+}
+pub async fn tag_rss(
+    State(summit): State<Arc<Summit>>,
+    WebPath(tag): WebPath<String>,
+    headers: HeaderMap,
+) -> Result<Response, WebError> {
+    tag_feed(&summit, &tag, FeedFormat::Rss, &headers).await
+}
+async fn tag_feed(
+    summit: &Summit,
+    tag: &str,
+    format: FeedFormat,
+    headers: &HeaderMap,
+) -> Result<Response, WebError> {
+    // Tags are stored normalized, see `content::ContentRefs`.
+    let tag = tag.trim_start_matches('#').to_lowercase();
+    let posts = summit.tag_posts(&tag, FEED_LEN).await?;
+    let feed = Feed {
+        config: summit.fedi_config(),
+        title: format!("#{tag}"),
+        page_url: summit.fedi_config().url(&format!("/t/{tag}")),
+        posts,
+    };
+    feed.respond(format, headers)
+}
+
+/// The posts of a feed, newest first.
+#[derive(Debug)]
+struct Feed<'a> {
+    config: &'a FediConfig,
+    title: String,
+    /// The absolute url of the html page this is a feed of.
+    page_url: String,
+    posts: Vec<Post>,
+}
+impl Feed<'_> {
+    /// The absolute url of the page of the given post.
+    fn post_url(&self, post: &Post) -> String {
+        self.config.url(&format!("/p/{}", post.id))
+    }
+    /// The time of the newest post, if any.
+    fn updated(&self) -> Option<DateTime> {
+        self.posts.iter().map(|post| post.created_on).max()
+    }
+    /// Respond with the feed, or `304 Not Modified` if the client's copy is current.
+    fn respond(&self, format: FeedFormat, headers: &HeaderMap) -> Result<Response, WebError> {
+        let feed_url = format!("{}/{}", self.page_url, format.file_name());
+        let mut body = String::new();
+        let res = match format {
+            FeedFormat::Atom => self.write_atom(&mut body, &feed_url),
+            FeedFormat::Rss => self.write_rss(&mut body, &feed_url),
+        };
+        res.map_err(|err| crate::Error::from(anyhow!("failed to write feed {feed_url}: {err}")))?;
+        let etag = etag(&body);
+        let last_modified = self.updated();
+        let not_modified = match headers.typed_get::<IfNoneMatch>() {
+            Some(if_none_match) => !if_none_match.precondition_passes(&etag),
+            // If-Modified-Since is only considered without If-None-Match, per RFC 9110.
+            None => match (headers.typed_get::<IfModifiedSince>(), last_modified) {
+                (Some(since), Some(last_modified)) => !since.is_modified(last_modified.into()),
+                _ => false,
+            },
+        };
+        let mut res = if not_modified {
+            StatusCode::NOT_MODIFIED.into_response()
+        } else {
+            body.into_response()
+        };
+        let res_headers = res.headers_mut();
+        res_headers.typed_insert(etag);
+        if let Some(last_modified) = last_modified {
+            res_headers.typed_insert(LastModified::from(SystemTime::from(last_modified)));
+        }
+        if !not_modified {
+            res_headers.typed_insert(ContentType::from(
+                format
+                    .content_type()
+                    .parse::<mime::Mime>()
+                    .expect("valid feed content type"),
+            ));
+        }
+        Ok(res)
+    }
+    fn write_atom(&self, w: &mut impl Write, feed_url: &str) -> fmt::Result {
+        writeln!(w, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+        writeln!(w, r#"<feed xmlns="http://www.w3.org/2005/Atom">"#)?;
+        writeln!(w, "  <id>{}</id>", Xml(feed_url))?;
+        writeln!(w, "  <title>{}</title>", Xml(&self.title))?;
+        // Atom requires an updated time, even of an empty feed.
+        let updated = self.updated().unwrap_or_default();
+        writeln!(w, "  <updated>{}</updated>", updated.iso())?;
+        writeln!(
+            w,
+            r#"  <link rel="self" type="application/atom+xml" href="{}"/>"#,
+            Xml(feed_url)
+        )?;
+        writeln!(
+            w,
+            r#"  <link rel="alternate" type="text/html" href="{}"/>"#,
+            Xml(&self.page_url)
+        )?;
+        for post in &self.posts {
+            writeln!(w, "  <entry>")?;
+            writeln!(w, "    <id>urn:uuid:{}</id>", post.id.hyphenated())?;
+            writeln!(w, "    <title>{}</title>", Xml(&post.title))?;
+            writeln!(w, "    <updated>{}</updated>", post.created_on.iso())?;
+            writeln!(w, "    <published>{}</published>", post.created_on.iso())?;
+            writeln!(
+                w,
+                "    <author><name>{}</name></author>",
+                Xml(&post.author.fedi_addr.to_string())
+            )?;
+            writeln!(
+                w,
+                r#"    <link rel="alternate" type="text/html" href="{}"/>"#,
+                Xml(&self.post_url(post))
+            )?;
+            writeln!(
+                w,
+                r#"    <content type="html">{}</content>"#,
+                Xml(&post_html(post))
+            )?;
+            writeln!(w, "  </entry>")?;
+        }
+        writeln!(w, "</feed>")
+    }
+    fn write_rss(&self, w: &mut impl Write, feed_url: &str) -> fmt::Result {
+        writeln!(w, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+        writeln!(
+            w,
+            r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">"#
+        )?;
+        writeln!(w, "  <channel>")?;
+        writeln!(w, "    <title>{}</title>", Xml(&self.title))?;
+        writeln!(w, "    <link>{}</link>", Xml(&self.page_url))?;
+        writeln!(
+            w,
+            "    <description>Posts of {}</description>",
+            Xml(&self.title)
+        )?;
+        writeln!(
+            w,
+            r#"    <atom:link rel="self" type="application/rss+xml" href="{}"/>"#,
+            Xml(feed_url)
+        )?;
+        if let Some(updated) = self.updated() {
+            writeln!(
+                w,
+                "    <lastBuildDate>{}</lastBuildDate>",
+                httpdate::fmt_http_date(updated.into())
+            )?;
+        }
+        for post in &self.posts {
+            writeln!(w, "    <item>")?;
+            writeln!(
+                w,
+                r#"      <guid isPermaLink="false">urn:uuid:{}</guid>"#,
+                post.id.hyphenated()
+            )?;
+            writeln!(w, "      <title>{}</title>", Xml(&post.title))?;
+            writeln!(w, "      <link>{}</link>", Xml(&self.post_url(post)))?;
+            writeln!(
+                w,
+                "      <pubDate>{}</pubDate>",
+                httpdate::fmt_http_date(post.created_on.into())
+            )?;
+            writeln!(
+                w,
+                "      <description>{}</description>",
+                Xml(&post_html(post))
+            )?;
+            writeln!(w, "    </item>")?;
+        }
+        writeln!(w, "  </channel>")?;
+        writeln!(w, "</rss>")
+    }
+}
+
+/// The html of a post, as rendered on its page.
+fn post_html(post: &Post) -> String {
+    MarkdownHtml::from(post.body.clone()).to_html()
+}
+/// A strong ETag of the given body.
+fn etag(body: &str) -> ETag {
+    let hash = Sha256::digest(body.as_bytes());
+    format!("\"{}\"", BASE64URL_NOPAD.encode(&hash[..16]))
+        .parse()
+        .expect("base64url is a valid etag")
+}
+
+/// Escape text for xml content and attribute values.
+struct Xml<'a>(&'a str);
+impl fmt::Display for Xml<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rest = self.0;
+        while let Some(i) = rest.find(['&', '<', '>', '"', '\'']) {
+            f.write_str(&rest[..i])?;
+            f.write_str(match rest.as_bytes()[i] {
+                b'&' => "&amp;",
+                b'<' => "&lt;",
+                b'>' => "&gt;",
+                b'"' => "&quot;",
+                _ => "&apos;",
+            })?;
+            rest = &rest[i + 1..];
+        }
+        f.write_str(rest)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        db::{Author, CreatePost},
+        dev::db::DevDb,
+        fedi::addr::FediAddr,
+        SummitConfig,
+    };
+    use axum::headers::HeaderValue;
+    use http::header;
+    use std::time::Duration;
+
+    async fn feed_of<'a>(summit: &'a Summit, titles: &[&str]) -> Feed<'a> {
+        for title in titles {
+            let author = Author {
+                fedi_addr: FediAddr::parse("alice@localhost:3000").unwrap(),
+            };
+            summit
+                .create_post(CreatePost {
+                    author,
+                    title: (*title).to_owned(),
+                    body: "Some **markdown** & <b>html</b>".into(),
+                })
+                .await
+                .unwrap();
+        }
+        Feed {
+            config: summit.fedi_config(),
+            title: "Tom & Jerry".into(),
+            page_url: summit.fedi_config().url("/c/summit"),
+            posts: summit.posts(None, FEED_LEN).await.unwrap(),
+        }
+    }
+    fn summit() -> Summit {
+        Summit::new(SummitConfig::default(), Box::<DevDb>::default())
+    }
+    async fn body(res: Response) -> String {
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+    /// Assert the xml is well formed, as far as elements nesting and text being escaped.
+    fn assert_well_formed(xml: &str) {
+        let xml = xml
+            .strip_prefix(r#"<?xml version="1.0" encoding="utf-8"?>"#)
+            .expect("xml declaration");
+        let mut open = Vec::new();
+        let mut rest = xml;
+        while let Some(start) = rest.find('<') {
+            assert_escaped(&rest[..start]);
+            let end = start + rest[start..].find('>').expect("unclosed tag");
+            let tag = &rest[start + 1..end];
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(open.pop(), Some(name), "mismatched closing tag in {xml}");
+            } else if !tag.ends_with('/') {
+                let name = tag.split_whitespace().next().expect("tag name");
+                open.push(name);
+            }
+            assert_escaped(tag.split_once(' ').map_or("", |(_, attrs)| attrs));
+            rest = &rest[end + 1..];
+        }
+        assert!(open.is_empty(), "unclosed {open:?} in {xml}");
+        assert_eq!(rest.trim(), "");
+    }
+    fn assert_escaped(text: &str) {
+        assert!(!text.contains(['<', '>']), "unescaped {text:?}");
+        for (i, _) in text.match_indices('&') {
+            let entity = &text[i..text[i..].find(';').map_or(text.len(), |end| i + end + 1)];
+            assert!(
+                ["&amp;", "&lt;", "&gt;", "&quot;", "&apos;"].contains(&entity),
+                "unescaped {text:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn well_formed_feeds() {
+        let summit = summit();
+        for titles in [&[][..], &["First", "<Second> & \"third\""]] {
+            let feed = feed_of(&summit, titles).await;
+            for format in [FeedFormat::Atom, FeedFormat::Rss] {
+                let res = feed.respond(format, &HeaderMap::new()).unwrap();
+                assert_eq!(res.status(), StatusCode::OK);
+                assert_eq!(res.headers()[header::CONTENT_TYPE], format.content_type());
+                let xml = body(res).await;
+                assert_well_formed(&xml);
+                let entry = match format {
+                    FeedFormat::Atom => "<entry>",
+                    FeedFormat::Rss => "<item>",
+                };
+                assert_eq!(xml.matches(entry).count(), feed.posts.len(), "{xml}");
+                assert!(xml.contains("Tom &amp; Jerry"), "{xml}");
+            }
+        }
+    }
+    #[tokio::test]
+    async fn not_modified() {
+        let summit = summit();
+        let feed = feed_of(&summit, &["First"]).await;
+        let res = feed.respond(FeedFormat::Atom, &HeaderMap::new()).unwrap();
+        let etag = res.headers()[header::ETAG].clone();
+        let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag.clone());
+        let res = feed.respond(FeedFormat::Atom, &headers).unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert!(!res.headers().contains_key(header::CONTENT_TYPE));
+        assert_eq!(res.headers()[header::ETAG], etag);
+        assert_eq!(body(res).await, "");
+        // Each format is its own representation.
+        let res = feed.respond(FeedFormat::Rss, &headers).unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MODIFIED_SINCE, last_modified);
+        let res = feed.respond(FeedFormat::Atom, &headers).unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        // If-Modified-Since is ignored given If-None-Match, even when it alone would match.
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"stale\""));
+        let res = feed.respond(FeedFormat::Atom, &headers).unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let future = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(3600));
+        headers.insert(header::IF_MODIFIED_SINCE, future.parse().unwrap());
+        let res = feed.respond(FeedFormat::Atom, &headers).unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn escapes_xml() {
+        assert_eq!(
+            Xml(r#"<a href="x">Tom & Jerry's</a>"#).to_string(),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+        );
+        assert_eq!(Xml("plain").to_string(), "plain");
+    }
+}
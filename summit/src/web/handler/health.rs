@@ -0,0 +1,105 @@
+use crate::{web::shutdown::ShutdownSignal, Summit};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+/// Liveness, the server is up and serving requests.
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Readiness, the server can serve requests fully. The `Db` is reachable and migrated, the
+/// delivery queue and content processing are running, and the server isn't draining.
+pub async fn readyz(
+    State((summit, shutdown_signal)): State<(Arc<Summit>, ShutdownSignal)>,
+) -> Response {
+    let mut failing = Vec::new();
+    if shutdown_signal.is_draining() {
+        failing.push("draining");
+    }
+    if let Err(err) = summit.check_db().await {
+        error!(?err, "db failed readiness check");
+        failing.push("db");
+    }
+    if !summit.delivery_queue().is_alive() {
+        failing.push("delivery queue");
+    }
+    if !summit.is_processing_content() {
+        failing.push("content processing");
+    }
+    if failing.is_empty() {
+        (StatusCode::OK, "ready").into_response()
+    } else {
+        warn!(?failing, "not ready");
+        let body = format!("not ready: {}", failing.join(", "));
+        (StatusCode::SERVICE_UNAVAILABLE, body).into_response()
+    }
+}
+
+/// Start draining, failing readiness checks so that load balancers stop routing here, while
+/// still serving any requests that arrive.
+pub async fn drain(State(shutdown_signal): State<ShutdownSignal>) -> StatusCode {
+    info!("draining on request");
+    shutdown_signal.drain();
+    StatusCode::NO_CONTENT
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{dev::db::DevDb, SummitConfig};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn ready_until_draining() {
+        let summit = Arc::new(Summit::new(
+            SummitConfig::default(),
+            Box::<DevDb>::default(),
+        ));
+        let shutdown_signal = ShutdownSignal::new(Duration::ZERO).await;
+        let state = || State((Arc::clone(&summit), shutdown_signal.clone()));
+        // The delivery queue hasn't polled yet.
+        assert_eq!(
+            readyz(state()).await.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        summit.delivery_queue().process_due(&summit).await.unwrap();
+        // Nor has content processing started.
+        assert_eq!(
+            readyz(state()).await.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        let stop_processing = ShutdownSignal::new(Duration::ZERO).await;
+        let content_processing = tokio::spawn({
+            let summit = Arc::clone(&summit);
+            let stop_processing = stop_processing.clone();
+            async move { summit.process_content(stop_processing).await }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(readyz(state()).await.status(), StatusCode::OK);
+        // Content processing stopping is noticed.
+        stop_processing.shutdown();
+        content_processing.await.unwrap();
+        assert_eq!(
+            readyz(state()).await.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        let content_processing = tokio::spawn({
+            let summit = Arc::clone(&summit);
+            let shutdown_signal = shutdown_signal.clone();
+            async move { summit.process_content(shutdown_signal).await }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(readyz(state()).await.status(), StatusCode::OK);
+        drain(State(shutdown_signal.clone())).await;
+        assert_eq!(
+            readyz(state()).await.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        content_processing.abort();
+    }
+}
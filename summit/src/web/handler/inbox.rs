@@ -0,0 +1,86 @@
+use crate::{fedi::signature::SignatureParams, Summit};
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, State},
+    http::{Request, StatusCode},
+};
+use http_body::{LengthLimitError, Limited};
+use hyper::Body;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// The largest activity accepted, as bodies are read in full before their signature is verified.
+pub const MAX_ACTIVITY_SIZE: usize = 1024 * 1024;
+
+/// A request body whose HTTP Signature has been verified.
+///
+/// Rejected requests are logged with the reason, under the request span.
+#[derive(Debug)]
+pub struct SignedBody {
+    pub signature: SignatureParams,
+    pub body: Bytes,
+}
+#[async_trait]
+impl FromRequest<Arc<Summit>, Body> for SignedBody {
+    type Rejection = StatusCode;
+    async fn from_request(
+        req: Request<Body>,
+        summit: &Arc<Summit>,
+    ) -> Result<Self, Self::Rejection> {
+        let (parts, body) = req.into_parts();
+        let body = hyper::body::to_bytes(Limited::new(body, MAX_ACTIVITY_SIZE))
+            .await
+            .map_err(|err| {
+                if err.downcast_ref::<LengthLimitError>().is_some() {
+                    warn!(
+                        limit = MAX_ACTIVITY_SIZE,
+                        "rejecting signed request, body too large"
+                    );
+                    StatusCode::PAYLOAD_TOO_LARGE
+                } else {
+                    warn!(?err, "rejecting signed request, failed to read body");
+                    StatusCode::BAD_REQUEST
+                }
+            })?;
+        match summit.verify_request(&parts, &body).await {
+            Ok(signature) => Ok(Self { signature, body }),
+            Err(err) => {
+                warn!(reason = %err, "rejecting signed request");
+                Err(err.status())
+            },
+        }
+    }
+}
+
+pub async fn handler(
+    State(_summit): State<Arc<Summit>>,
+    SignedBody { signature, body }: SignedBody,
+) -> StatusCode {
+    // TODO: Process activities. For now verified deliveries are only acknowledged.
+    info!(
+        key_id = signature.key_id,
+        body_len = body.len(),
+        "received signed inbox delivery"
+    );
+    StatusCode::ACCEPTED
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{dev::db::DevDb, SummitConfig};
+
+    #[tokio::test]
+    async fn rejects_oversized_bodies() {
+        let summit = Arc::new(Summit::new(
+            SummitConfig::default(),
+            Box::<DevDb>::default(),
+        ));
+        let req = Request::post("/inbox")
+            .body(Body::from(vec![b' '; MAX_ACTIVITY_SIZE + 1]))
+            .unwrap();
+        let err = SignedBody::from_request(req, &summit).await.unwrap_err();
+        assert_eq!(err, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -1,21 +1,25 @@
 use crate::{
-    date_time::TimeZone,
+    date_time::DateTimePrefs,
     uuid::{RequestId, UserId},
-    web::{handler::community::CommunityPost, shutdown::ShutdownSignal},
-    Summit,
+    web::{extension::viewer::Viewer, handler::community::CommunityPost, shutdown::ShutdownSignal},
+    Summit, UserEvent,
 };
 use axum::{
-    extract::State,
+    extract::{Query, State},
     response::sse::{Event, Sse},
     Extension,
 };
 use futures::stream::Stream;
 use kanal::ReceiveError;
 use sailfish::{RenderError, TemplateOnce};
+use serde::Deserialize;
 use std::{convert::Infallible, pin::pin, sync::Arc, time::Duration};
 use thiserror::Error;
 use tracing::{error, info, trace, Span};
 
+/// How long clients wait before reconnecting, after the stream closes for shutdown.
+const RECONNECT_AFTER: Duration = Duration::from_secs(2);
+
 #[derive(Debug, Error)]
 enum EventError {
     #[error("receiving event")]
@@ -24,39 +28,62 @@ enum EventError {
     Render(#[from] RenderError),
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct LiveQuery {
+    /// The community of the page, whose time zone posts are shown in, as on the page itself.
+    pub community: Option<String>,
+}
+
 pub async fn live_handler(
     State((summit, shutdown_signal)): State<(Arc<Summit>, ShutdownSignal)>,
-    Extension(_req_id): Extension<RequestId>,
+    Extension(req_id): Extension<RequestId>,
+    Extension(viewer): Extension<Viewer>,
+    Query(query): Query<LiveQuery>,
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
-    // No users are registered yet. Use a default.
-    let user_id = UserId::default();
-    let user_tz = TimeZone::default();
+    let user_id = viewer.user_id();
+    let community = match &query.community {
+        Some(name) => summit.community(name).await.unwrap_or_else(|err| {
+            error!(?err, name, "failed to look up community of event stream");
+            None
+        }),
+        None => None,
+    };
+    let date_prefs = viewer.prefs.date_time_in(
+        community
+            .and_then(|community| community.time_zone)
+            .unwrap_or_default(),
+    );
 
     info!(%user_id, "starting sse connection");
-    let user_events = summit.user_events(user_id);
-
     let stream = async_stream::stream! {
+        let _connection = summit.metrics().sse_connections.track();
         let mut conn_guard = ConnectionGuard {
             span: Span::current(),
             closed_via_shutdown_signal: false,
         };
+        let user_events = match summit.open_event_stream(user_id, req_id) {
+            Ok(user_events) => user_events,
+            Err(err) => {
+                error!(?err, "failed to open event stream");
+                return;
+            }
+        };
+        let _stream_guard = EventStreamGuard {
+            summit: Arc::clone(&summit),
+            user_id,
+            req_id,
+        };
         loop {
             tokio::select! {
                 event_res = &mut pin!(user_events.recv()) => {
-                    let res = event_res.map_or_else(
-                        |err| Err(EventError::from(err)),
-                        |post| -> Result<_, EventError> {
-                            // For now "events" are just posts.
-                            Ok(CommunityPost::new(user_tz, post).render_once()?)
-                        },
-                    );
+                    let res = event_res
+                        .map_err(EventError::from)
+                        .and_then(|event| render_event(date_prefs, event));
                     match res {
-                        Ok(rendered_event_html) => {
-                            yield Ok(
-                                Event::default()
-                                    .event("newCommunityPost")
-                                    .data(rendered_event_html)
-                            );
+                        Ok(event) => yield Ok(event),
+                        Err(EventError::Receive(err)) => {
+                            error!(?err, "event stream closed");
+                            return;
                         }
                         Err(err) => {
                             error!(?err, "broadcasting user event failed");
@@ -65,6 +92,8 @@ pub async fn live_handler(
                 }
                 () = &mut pin!(shutdown_signal.recv()) => {
                     conn_guard.closed_via_shutdown_signal = true;
+                    // Tell the client to reconnect, such as to another instance or once restarted.
+                    yield Ok(Event::default().event("reconnect").data("").retry(RECONNECT_AFTER));
                     return;
                 }
             }
@@ -77,6 +106,36 @@ pub async fn live_handler(
             .text("keep-alive-text"),
     )
 }
+fn render_event(date_prefs: DateTimePrefs, event: UserEvent) -> Result<Event, EventError> {
+    let event =
+        match event {
+            UserEvent::Post(post) => Event::default()
+                .event("newCommunityPost")
+                .data(CommunityPost::new(date_prefs, post).render_once()?),
+            // Swapped into the header badge, which is empty when there's nothing unread.
+            UserEvent::Notifications { unread } => Event::default()
+                .event("notificationBadge")
+                .data(if unread > 0 {
+                    unread.to_string()
+                } else {
+                    String::new()
+                }),
+        };
+    Ok(event)
+}
+/// Closes the event stream of a SSE connection once it's dropped, so events stop being sent to it.
+struct EventStreamGuard {
+    summit: Arc<Summit>,
+    user_id: UserId,
+    req_id: RequestId,
+}
+impl Drop for EventStreamGuard {
+    fn drop(&mut self) {
+        if let Err(err) = self.summit.close_event_stream(self.user_id, self.req_id) {
+            error!(?err, "failed to close event stream");
+        }
+    }
+}
 /// A guard to report when a SSE Stream has closed, and and metadata we attach to that stream.
 pub(super) struct ConnectionGuard {
     pub span: Span,
@@ -0,0 +1,12 @@
+use crate::{web::error::WebError, Summit};
+use axum::{extract::State, http::header, response::IntoResponse};
+use std::sync::Arc;
+
+/// The content type of the Prometheus text exposition format.
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Serve the metrics of this instance, for Prometheus to scrape.
+pub async fn handler(State(summit): State<Arc<Summit>>) -> Result<impl IntoResponse, WebError> {
+    let metrics = summit.encode_metrics().await?;
+    Ok(([(header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)], metrics))
+}
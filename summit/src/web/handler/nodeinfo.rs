@@ -0,0 +1,125 @@
+use crate::Summit;
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::error;
+
+pub const NODEINFO_SCHEMA: &str = "http://nodeinfo.diaspora.software/ns/schema/2.1";
+
+/// The `/.well-known/nodeinfo` discovery document, pointing to the versioned NodeInfo documents.
+#[derive(Debug, Serialize)]
+pub struct Discovery {
+    pub links: Vec<DiscoveryLink>,
+}
+#[derive(Debug, Serialize)]
+pub struct DiscoveryLink {
+    pub rel: &'static str,
+    pub href: String,
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfo {
+    pub version: &'static str,
+    pub software: Software,
+    pub protocols: &'static [&'static str],
+    pub services: Services,
+    pub open_registrations: bool,
+    pub usage: Usage,
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+}
+#[derive(Debug, Serialize)]
+pub struct Software {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub repository: &'static str,
+}
+#[derive(Debug, Default, Serialize)]
+pub struct Services {
+    pub inbound: &'static [&'static str],
+    pub outbound: &'static [&'static str],
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub users: UsageUsers,
+    pub local_posts: u64,
+}
+#[derive(Debug, Serialize)]
+pub struct UsageUsers {
+    pub total: u64,
+}
+
+pub async fn discovery_handler(State(summit): State<Arc<Summit>>) -> Json<Discovery> {
+    Json(Discovery {
+        links: vec![DiscoveryLink {
+            rel: NODEINFO_SCHEMA,
+            href: summit.fedi_config().url("/nodeinfo/2.1"),
+        }],
+    })
+}
+
+pub async fn handler(State(summit): State<Arc<Summit>>) -> Result<Json<NodeInfo>, StatusCode> {
+    let stats = summit.stats().await.map_err(|err| {
+        error!(?err, "failed to load nodeinfo stats");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(NodeInfo {
+        version: "2.1",
+        software: Software {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            repository: "https://github.com/leeola/summit",
+        },
+        protocols: &["activitypub"],
+        services: Services::default(),
+        open_registrations: false,
+        usage: Usage {
+            users: UsageUsers { total: stats.users },
+            local_posts: stats.posts,
+        },
+        metadata: Default::default(),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        db::{Author, CreatePost},
+        dev::db::DevDb,
+        fedi::addr::FediAddr,
+        SummitConfig,
+    };
+
+    #[tokio::test]
+    async fn usage_from_stats() {
+        let summit = Arc::new(Summit::new(
+            SummitConfig::default(),
+            Box::<DevDb>::default(),
+        ));
+        for user in ["alice", "alice", "bob"] {
+            let author = Author {
+                fedi_addr: FediAddr::parse(&format!("{user}@localhost:3000")).unwrap(),
+            };
+            summit
+                .create_post(CreatePost {
+                    author,
+                    title: "Title".into(),
+                    body: "hi".into(),
+                })
+                .await
+                .unwrap();
+        }
+        let stats = summit.stats().await.unwrap();
+        let Json(nodeinfo) = handler(State(Arc::clone(&summit))).await.unwrap();
+        assert_eq!(nodeinfo.usage.users.total, stats.users);
+        assert_eq!(nodeinfo.usage.local_posts, stats.posts);
+        assert_eq!(nodeinfo.usage.local_posts, 3);
+        let Json(discovery) = discovery_handler(State(summit)).await;
+        assert_eq!(discovery.links[0].rel, NODEINFO_SCHEMA);
+        assert_eq!(
+            discovery.links[0].href,
+            "http://localhost:3000/nodeinfo/2.1"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{
+    date_time::DateTime,
+    db::{Notification, NotificationKind},
+    uuid::NotificationId,
+    web::{
+        error::{WebError, WebQuery},
+        extension::viewer::Viewer,
+        template::{meta::PageMeta, Template},
+    },
+    Summit,
+};
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+    Extension,
+};
+use sailfish::TemplateOnce;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// The number of notifications listed per page.
+const PER_PAGE: usize = 100;
+
+#[derive(Debug, TemplateOnce)]
+#[template(path = "page/notifications.stpl")]
+pub struct NotificationsPage {
+    pub title: String,
+    pub viewer: Viewer,
+    pub meta: PageMeta,
+    pub notifications: Vec<NotificationItem>,
+    /// Whether this is an older page, rather than the newest notifications.
+    pub paged: bool,
+    /// The cursor of the next older page, if any.
+    pub older: Option<NotificationId>,
+}
+#[derive(Debug, Default, Deserialize)]
+pub struct NotificationsQuery {
+    /// List notifications older than this one, rather than the newest.
+    pub before: Option<NotificationId>,
+}
+#[derive(Debug)]
+pub struct NotificationItem {
+    pub summary: String,
+    pub created_on: DateTime,
+    pub read: bool,
+}
+impl From<Notification> for NotificationItem {
+    fn from(notification: Notification) -> Self {
+        let summary = match notification.kind {
+            NotificationKind::Mention { by, .. } => format!("{by} mentioned you"),
+            NotificationKind::Reply { by, .. } => format!("{by} replied to you"),
+            NotificationKind::VoteMilestone { votes, .. } => {
+                format!("Your post reached {votes} votes")
+            },
+            NotificationKind::Moderation {
+                action,
+                reason: Some(reason),
+            } => format!("A moderator {action}: {reason}"),
+            NotificationKind::Moderation {
+                action,
+                reason: None,
+            } => format!("A moderator {action}"),
+        };
+        Self {
+            summary,
+            created_on: notification.created_on,
+            read: notification.read,
+        }
+    }
+}
+
+pub async fn handler(
+    State(summit): State<Arc<Summit>>,
+    WebQuery(query): WebQuery<NotificationsQuery>,
+    Extension(viewer): Extension<Viewer>,
+) -> Result<Template<NotificationsPage>, WebError> {
+    if viewer.user.is_none() {
+        return Err(WebError::Unauthorized);
+    }
+    // Fetch one extra, to know whether there's an older page.
+    let mut notifications = summit
+        .notifications(viewer.user_id(), query.before, PER_PAGE + 1)
+        .await?;
+    let older = if notifications.len() > PER_PAGE {
+        notifications.truncate(PER_PAGE);
+        notifications.last().map(|notification| notification.id)
+    } else {
+        None
+    };
+    Ok(Template(NotificationsPage {
+        title: "Notifications".into(),
+        viewer,
+        meta: PageMeta::new("Notifications"),
+        notifications: notifications.into_iter().map(Into::into).collect(),
+        paged: query.before.is_some(),
+        older,
+    }))
+}
+/// Mark all notifications of the viewer as read.
+pub async fn read_handler(
+    State(summit): State<Arc<Summit>>,
+    Extension(viewer): Extension<Viewer>,
+) -> Result<impl IntoResponse, WebError> {
+    if viewer.user.is_none() {
+        return Err(WebError::Unauthorized);
+    }
+    summit
+        .mark_notifications_read(viewer.user_id(), None)
+        .await?;
+    Ok(Redirect::to("/notifications"))
+}
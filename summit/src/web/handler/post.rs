@@ -0,0 +1,39 @@
+use crate::{
+    uuid::PostId,
+    web::{
+        error::{WebError, WebPath},
+        extension::viewer::Viewer,
+        handler::community::CommunityPost,
+        template::{meta::PageMeta, Template},
+    },
+    Summit,
+};
+use axum::{extract::State, Extension};
+use sailfish::TemplateOnce;
+use std::sync::Arc;
+
+#[derive(Debug, TemplateOnce)]
+#[template(path = "page/post.stpl")]
+pub struct PostPage {
+    pub title: String,
+    pub viewer: Viewer,
+    pub meta: PageMeta,
+    pub post: CommunityPost,
+}
+
+/// Serve the page of a single post, such as for permalinks and link previews.
+pub async fn handler(
+    State(summit): State<Arc<Summit>>,
+    WebPath(id): WebPath<PostId>,
+    Extension(viewer): Extension<Viewer>,
+) -> Result<Template<PostPage>, WebError> {
+    let post = summit.post(id).await?.ok_or(WebError::NotFound)?;
+    let meta = PageMeta::post(summit.fedi_config(), &post);
+    let date_prefs = viewer.prefs.date_time();
+    Ok(Template(PostPage {
+        title: meta.title.clone(),
+        viewer,
+        meta,
+        post: CommunityPost::new(date_prefs, post),
+    }))
+}
@@ -0,0 +1,120 @@
+use crate::{
+    date_time::{DateTimeFormat, TimeZone},
+    db::{Preferences, Theme},
+    web::{
+        error::{WebError, WebForm},
+        extension::viewer::Viewer,
+        template::{meta::PageMeta, Template},
+    },
+    Summit,
+};
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+    Extension,
+};
+use sailfish::TemplateOnce;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, TemplateOnce)]
+#[template(path = "page/preferences.stpl")]
+pub struct PreferencesPage {
+    pub title: String,
+    pub viewer: Viewer,
+    pub meta: PageMeta,
+    /// A just created API token, shown only once.
+    pub new_token: Option<String>,
+    /// Submitted with the API token form, which is only shown in dev.
+    pub csrf_token: String,
+}
+#[derive(Debug, Deserialize)]
+pub struct PreferencesForm {
+    /// An IANA zone name or fixed offset, or empty to use the community default.
+    pub time_zone: String,
+    pub date_time_format: DateTimeFormat,
+    pub theme: Theme,
+    pub posts_per_page: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenForm {
+    pub csrf_token: String,
+}
+
+pub async fn handler(
+    State(summit): State<Arc<Summit>>,
+    Extension(viewer): Extension<Viewer>,
+) -> Result<Template<PreferencesPage>, WebError> {
+    if viewer.user.is_none() {
+        return Err(WebError::Unauthorized);
+    }
+    Ok(Template(PreferencesPage {
+        title: "Preferences".into(),
+        csrf_token: summit.csrf_token(viewer.user_id()),
+        viewer,
+        meta: PageMeta::new("Preferences"),
+        new_token: None,
+    }))
+}
+/// Create an API token for the viewer, showing it once.
+///
+/// Only routed in dev, as viewers are otherwise anonymous until real sessions exist.
+pub async fn create_token_handler(
+    State(summit): State<Arc<Summit>>,
+    Extension(viewer): Extension<Viewer>,
+    WebForm(form): WebForm<TokenForm>,
+) -> Result<Template<PreferencesPage>, WebError> {
+    if viewer.user.is_none() {
+        return Err(WebError::Unauthorized);
+    }
+    if !summit.verify_csrf_token(viewer.user_id(), &form.csrf_token) {
+        return Err(WebError::Forbidden("invalid csrf token"));
+    }
+    let token = summit.create_api_token(viewer.user_id()).await?;
+    Ok(Template(PreferencesPage {
+        title: "Preferences".into(),
+        csrf_token: summit.csrf_token(viewer.user_id()),
+        viewer,
+        meta: PageMeta::new("Preferences"),
+        new_token: Some(token),
+    }))
+}
+pub async fn save_handler(
+    State(summit): State<Arc<Summit>>,
+    Extension(viewer): Extension<Viewer>,
+    WebForm(form): WebForm<PreferencesForm>,
+) -> Result<impl IntoResponse, WebError> {
+    if viewer.user.is_none() {
+        return Err(WebError::Unauthorized);
+    }
+    let PreferencesForm {
+        time_zone,
+        date_time_format,
+        theme,
+        posts_per_page,
+    } = form;
+    let time_zone = match time_zone.as_str() {
+        "" => None,
+        time_zone => Some(
+            time_zone
+                .parse::<TimeZone>()
+                .map_err(|err| WebError::BadRequest(format!("invalid time zone: {err}")))?,
+        ),
+    };
+    if !(1..=Preferences::MAX_POSTS_PER_PAGE).contains(&posts_per_page) {
+        return Err(WebError::BadRequest(format!(
+            "invalid posts per page: {posts_per_page}"
+        )));
+    }
+    let preferences = Preferences {
+        time_zone,
+        date_time_format,
+        theme,
+        posts_per_page,
+    };
+    summit
+        .set_preferences(viewer.user_id(), preferences)
+        .await?;
+    Ok(Redirect::to("/preferences"))
+}
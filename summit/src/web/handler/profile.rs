@@ -0,0 +1,229 @@
+use crate::{
+    date_time::DateTime,
+    db::{FediAddr, Profile},
+    uuid::PostId,
+    web::{
+        error::{WebError, WebPath, WebQuery},
+        extension::viewer::Viewer,
+        handler::{actor, community::CommunityPost, feed::FeedLink},
+        template::{meta::PageMeta, MarkdownHtml, Template},
+    },
+    Summit,
+};
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use sailfish::TemplateOnce;
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::debug;
+
+#[derive(Debug, TemplateOnce)]
+#[template(path = "page/profile.stpl")]
+pub struct ProfilePage {
+    pub title: String,
+    pub viewer: Viewer,
+    pub meta: PageMeta,
+    pub fedi_addr: FediAddr,
+    pub display_name: String,
+    pub bio_html: Option<MarkdownHtml>,
+    pub avatar_url: Option<String>,
+    pub joined_on: Option<DateTime>,
+    pub posts: Vec<CommunityPost>,
+    /// Whether this is an older page, rather than the newest posts.
+    pub paged: bool,
+    /// The cursor of the next older page, if any.
+    pub older: Option<PostId>,
+}
+#[derive(Debug, Default, Deserialize)]
+pub struct ProfileQuery {
+    /// List posts older than this one, rather than the newest.
+    pub before: Option<PostId>,
+}
+
+/// Resolve the `name` of a `/u/:name` path, either a local `user` or any `user@host`.
+pub fn profile_addr(summit: &Summit, name: &str) -> Result<FediAddr, WebError> {
+    FediAddr::parse_or_local(name, summit.fedi_config().host()).map_err(|err| {
+        debug!(%err, name, "invalid profile address");
+        WebError::NotFound
+    })
+}
+
+/// Serve the profile page of a local `user`, or any known `user@host`.
+///
+/// ActivityPub requests for local users are served the actor document instead.
+//
+// TODO: List replies alongside posts, once they exist.
+pub async fn handler(
+    State(summit): State<Arc<Summit>>,
+    WebPath(name): WebPath<String>,
+    WebQuery(query): WebQuery<ProfileQuery>,
+    Extension(viewer): Extension<Viewer>,
+    headers: HeaderMap,
+) -> Result<Response, WebError> {
+    if actor::wants_activity_json(&headers) {
+        return Ok(actor::vary_accept(
+            actor::user_handler(State(summit), Path(name))
+                .await
+                .into_response(),
+        ));
+    }
+    let addr = profile_addr(&summit, &name)?;
+    let profile = summit.profile(&addr).await?.ok_or(WebError::NotFound)?;
+    let per_page = viewer.prefs.posts_per_page();
+    // Fetch one extra, to know whether there's an older page.
+    let mut posts = summit.user_posts(&addr, query.before, per_page + 1).await?;
+    let older = if posts.len() > per_page {
+        posts.truncate(per_page);
+        posts.last().map(|post| post.id)
+    } else {
+        None
+    };
+    let date_prefs = viewer.prefs.date_time();
+    let display_name = profile
+        .display_name
+        .clone()
+        .unwrap_or_else(|| profile.fedi_addr.user.to_string());
+    let meta = PageMeta {
+        feed: Some(FeedLink::user(&name)),
+        ..PageMeta::profile(summit.fedi_config(), &profile, &display_name)
+    }
+    .with_postings(summit.fedi_config(), &posts);
+    let Profile {
+        fedi_addr,
+        bio,
+        avatar_url,
+        joined_on,
+        ..
+    } = profile;
+    Ok(actor::vary_accept(
+        Template(ProfilePage {
+            title: display_name.clone(),
+            viewer,
+            meta,
+            fedi_addr,
+            display_name,
+            bio_html: bio.map(MarkdownHtml::from),
+            avatar_url,
+            joined_on,
+            posts: posts
+                .into_iter()
+                .map(|post| CommunityPost::new(date_prefs, post))
+                .collect(),
+            paged: query.before.is_some(),
+            older,
+        })
+        .into_response(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        db::{Author, CreatePost},
+        dev::db::DevDb,
+        SummitConfig,
+    };
+    use axum::http::{header, StatusCode};
+
+    async fn summit_with_posts(posts: &[(&str, &str)]) -> Arc<Summit> {
+        let summit = Arc::new(Summit::new(
+            SummitConfig::default(),
+            Box::<DevDb>::default(),
+        ));
+        for (addr, title) in posts {
+            let author = Author {
+                fedi_addr: FediAddr::parse(addr).unwrap(),
+            };
+            summit
+                .create_post(CreatePost {
+                    author,
+                    title: (*title).to_owned(),
+                    body: "hi".into(),
+                })
+                .await
+                .unwrap();
+        }
+        summit
+    }
+    async fn get(
+        summit: &Arc<Summit>,
+        name: &str,
+        before: Option<PostId>,
+    ) -> Result<String, WebError> {
+        let mut viewer = Viewer::default();
+        viewer.prefs.posts_per_page = 2;
+        let res = handler(
+            State(Arc::clone(summit)),
+            WebPath(name.to_owned()),
+            WebQuery(ProfileQuery { before }),
+            Extension(viewer),
+            HeaderMap::new(),
+        )
+        .await?;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::VARY], "accept");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        Ok(String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn resolves_profile_addrs() {
+        let summit = Summit::new(SummitConfig::default(), Box::<DevDb>::default());
+        let addr = |name| profile_addr(&summit, name).map(|addr| addr.to_string());
+        assert_eq!(addr("alice").unwrap(), "@alice@localhost:3000");
+        assert_eq!(addr("bob@Remote.Example").unwrap(), "@bob@remote.example");
+        assert!(matches!(addr("not@an@addr"), Err(WebError::NotFound)));
+        assert!(matches!(addr(""), Err(WebError::NotFound)));
+    }
+    #[tokio::test]
+    async fn local_and_remote_profiles() {
+        let summit = summit_with_posts(&[
+            ("alice@localhost:3000", "By alice"),
+            ("bob@remote.example", "By bob"),
+        ])
+        .await;
+        let body = get(&summit, "alice", None).await.unwrap();
+        assert!(
+            body.contains("By alice") && !body.contains("By bob"),
+            "{body}"
+        );
+        let body = get(&summit, "bob@remote.example", None).await.unwrap();
+        assert!(
+            body.contains("By bob") && !body.contains("By alice"),
+            "{body}"
+        );
+        for name in ["nobody", "nobody@remote.example", "not@an@addr"] {
+            assert!(
+                matches!(get(&summit, name, None).await, Err(WebError::NotFound)),
+                "{name}"
+            );
+        }
+    }
+    #[tokio::test]
+    async fn pages_with_before() {
+        let summit = summit_with_posts(&[
+            ("alice@localhost:3000", "First"),
+            ("alice@localhost:3000", "Second"),
+            ("alice@localhost:3000", "Third"),
+        ])
+        .await;
+        let body = get(&summit, "alice", None).await.unwrap();
+        assert!(body.contains("Third") && body.contains("Second"), "{body}");
+        assert!(!body.contains("First"), "{body}");
+        let older = body
+            .split("?before=")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .expect("a link to older posts");
+        let body = get(&summit, "alice", Some(older.parse().unwrap()))
+            .await
+            .unwrap();
+        assert!(body.contains("First") && !body.contains("Second"), "{body}");
+        assert!(!body.contains("?before="), "{body}");
+    }
+}
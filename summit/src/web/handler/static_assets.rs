@@ -1,78 +1,255 @@
-use axum::{extract::Path, headers::ContentType, http::StatusCode, TypedHeader};
-use mime::{TEXT_CSS, TEXT_JAVASCRIPT};
+use crate::web::{compression::Encoding, error::WebError};
+use axum::{
+    extract::Path,
+    headers::{ContentType, ETag, HeaderMapExt, IfNoneMatch},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
 use std::{
+    borrow::Cow,
     collections::HashMap,
     ops::{Deref, DerefMut},
     sync::OnceLock,
 };
-use tracing::debug;
+use tracing::{debug, error};
 
 /// A static collection of assets distributed in the binary.
 static ASSETS: OnceLock<StaticAssets> = OnceLock::new();
 
-pub async fn serve_asset(
-    Path(asset_path): Path<String>,
-) -> (StatusCode, TypedHeader<ContentType>, &'static [u8]) {
+/// The assets listed by `build.rs`, see `BuildConfig::assets`.
+static GENERATED_ASSETS: &[GeneratedAsset] = &include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+/// Unhashed asset urls are cached briefly, as their content may change with any deploy.
+const UNHASHED_CACHE_CONTROL: &str = "public, max-age=300";
+/// The content of hashed asset urls never changes, so they're cached for as long as allowed.
+const HASHED_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+pub async fn serve_asset(Path(asset_path): Path<String>, headers: HeaderMap) -> Response {
     debug!(asset_path, "serving asset");
 
-    if let Some((content_type, asset)) = ASSETS
+    let Some(asset) = ASSETS
         .get_or_init(StaticAssets::new)
         .get(asset_path.as_str())
-    {
-        (StatusCode::OK, TypedHeader(content_type.clone()), asset)
+    else {
+        return WebError::NotFound.into_response();
+    };
+    let encoding = Encoding::negotiate(&headers);
+    let (bytes, encoding) = asset.generated.encoded(encoding);
+    // Each encoding is a distinct representation, so needs a distinct strong etag.
+    let etag =
+        match format!("\"{}{}\"", asset.generated.hash, etag_suffix(encoding)).parse::<ETag>() {
+            Ok(etag) => etag,
+            Err(err) => {
+                error!(?err, asset_path, "invalid asset etag");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            },
+        };
+    let cache_control = if asset.hashed {
+        HASHED_CACHE_CONTROL
+    } else {
+        UNHASHED_CACHE_CONTROL
+    };
+    let not_modified = headers
+        .typed_get::<IfNoneMatch>()
+        .map_or(false, |if_none_match| {
+            !if_none_match.precondition_passes(&etag)
+        });
+    let mut res = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
     } else {
-        // TODO: Show global 404?
-        (StatusCode::NOT_FOUND, TypedHeader(ContentType::text()), &[])
+        bytes.into_response()
+    };
+    let res_headers = res.headers_mut();
+    res_headers.insert(
+        header::VARY,
+        HeaderValue::from_static(header::ACCEPT_ENCODING.as_str()),
+    );
+    if let Some(content_encoding) = encoding.content_encoding() {
+        res_headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(content_encoding),
+        );
+    }
+    res_headers.typed_insert(etag);
+    res_headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(cache_control),
+    );
+    if !not_modified {
+        res_headers.typed_insert(asset.content_type.clone());
     }
+    res
 }
 
-macro_rules! insert_vendor_asset {
-    ($assets_map:expr, $content_type:expr, $vendor_path:expr$(,)?) => {
-        $assets_map.insert(
-            $vendor_path,
-            (
-                $content_type,
-                include_bytes!(concat!(env!("VENDOR_DIR"), "/", $vendor_path)),
-            ),
-        );
-    };
+/// The url of the given asset, such as `style.css`, including a hash of its content so that it can
+/// be cached indefinitely.
+///
+/// Unknown assets are linked unhashed.
+pub fn asset_url(asset_path: &str) -> Cow<'static, str> {
+    if cfg!(feature = "local_dev") {
+        // Served from disk, so the hash of the built content may be stale.
+        return Cow::Owned(format!("/static/{asset_path}"));
+    }
+    match ASSETS.get_or_init(StaticAssets::new).urls.get(asset_path) {
+        Some(url) => Cow::Borrowed(url.as_str()),
+        None => {
+            error!(asset_path, "linking unknown asset");
+            Cow::Owned(format!("/static/{asset_path}"))
+        },
+    }
+}
+
+/// The subresource integrity of the given asset, for the `integrity` attribute of the element
+/// linking it. Such as `sha384-<base64>`.
+///
+/// Unknown assets have no integrity, which browsers treat as unchecked.
+pub fn asset_integrity(asset_path: &str) -> &'static str {
+    if cfg!(feature = "local_dev") {
+        // Served from disk, so may no longer match the built content.
+        return "";
+    }
+    match ASSETS.get_or_init(StaticAssets::new).get(asset_path) {
+        Some(asset) => asset.generated.integrity,
+        None => {
+            error!(asset_path, "linking unknown asset");
+            ""
+        },
+    }
 }
+
+/// The plain path and content type of the asset at the given plain or hashed path, for serving it
+/// from disk in `local_dev`.
+#[cfg(feature = "local_dev")]
+pub fn asset_source(asset_path: &str) -> Option<(&'static str, ContentType)> {
+    ASSETS
+        .get_or_init(StaticAssets::new)
+        .get(asset_path)
+        .map(|asset| (asset.generated.path, asset.content_type.clone()))
+}
+
+/// An asset as listed by `build.rs`.
 #[derive(Debug)]
-struct StaticAssets(HashMap<&'static str, (ContentType, &'static [u8])>);
+struct GeneratedAsset {
+    /// The path served under `/static/`, without a hash.
+    path: &'static str,
+    content_type: &'static str,
+    /// A hex hash of the content.
+    hash: &'static str,
+    /// The subresource integrity of the content.
+    integrity: &'static str,
+    bytes: &'static [u8],
+    gzip: &'static [u8],
+    brotli: &'static [u8],
+}
+impl GeneratedAsset {
+    /// The content in the given encoding, or unencoded if that wouldn't be any smaller.
+    fn encoded(&self, encoding: Encoding) -> (&'static [u8], Encoding) {
+        let encoded = match encoding {
+            Encoding::Brotli => self.brotli,
+            Encoding::Gzip => self.gzip,
+            Encoding::Identity => self.bytes,
+        };
+        if encoded.len() < self.bytes.len() {
+            (encoded, encoding)
+        } else {
+            (self.bytes, Encoding::Identity)
+        }
+    }
+}
+#[derive(Debug)]
+struct Asset {
+    generated: &'static GeneratedAsset,
+    content_type: ContentType,
+    /// Whether this is served from the hashed url, rather than the plain path.
+    hashed: bool,
+}
+/// The assets, by both their plain and hashed paths.
+#[derive(Debug)]
+struct StaticAssets {
+    assets: HashMap<String, Asset>,
+    /// The hashed url of each asset, by plain path.
+    urls: HashMap<&'static str, String>,
+}
 impl StaticAssets {
     pub fn new() -> Self {
-        let mut assets = Self(Default::default());
-        assets.insert(
-            "style.css",
-            (
-                ContentType::from(TEXT_CSS),
-                include_bytes!(concat!(env!("CSS_DIR"), "/style.css")),
-            ),
-        );
-        #[cfg(feature = "local_dev")]
-        assets.insert(
-            "dev_restart.js",
-            (
-                ContentType::from(TEXT_JAVASCRIPT),
-                include_bytes!(concat!(env!("STATIC_DIR"), "/dev_restart.js")),
-            ),
-        );
-        insert_vendor_asset!(
-            assets,
-            ContentType::from(TEXT_JAVASCRIPT),
-            "htmx-1.9.2/htmx.min.js",
-        );
+        let mut assets = Self {
+            assets: Default::default(),
+            urls: Default::default(),
+        };
+        for generated in GENERATED_ASSETS {
+            let content_type = generated
+                .content_type
+                .parse::<mime::Mime>()
+                .map(ContentType::from)
+                .unwrap_or_else(|_| ContentType::octet_stream());
+            let hashed_path = hashed_path(generated.path, generated.hash);
+            assets
+                .urls
+                .insert(generated.path, format!("/static/{hashed_path}"));
+            assets.insert(
+                hashed_path,
+                Asset {
+                    generated,
+                    content_type: content_type.clone(),
+                    hashed: true,
+                },
+            );
+            assets.insert(
+                generated.path.to_owned(),
+                Asset {
+                    generated,
+                    content_type,
+                    hashed: false,
+                },
+            );
+        }
         assets
     }
 }
 impl Deref for StaticAssets {
-    type Target = HashMap<&'static str, (ContentType, &'static [u8])>;
+    type Target = HashMap<String, Asset>;
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.assets
     }
 }
 impl DerefMut for StaticAssets {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.assets
+    }
+}
+
+/// Distinguishes the etag of each encoding, as each is a distinct representation.
+fn etag_suffix(encoding: Encoding) -> &'static str {
+    match encoding {
+        Encoding::Brotli => "-br",
+        Encoding::Gzip => "-gz",
+        Encoding::Identity => "",
+    }
+}
+/// Insert the hash before the extension of the file name, eg `style.<hash>.css`.
+fn hashed_path(path: &str, hash: &str) -> String {
+    let (dir, file_name) = path
+        .rsplit_once('/')
+        .map_or(("", path), |(dir, file_name)| {
+            (&path[..dir.len() + 1], file_name)
+        });
+    match file_name.split_once('.') {
+        Some((stem, ext)) => format!("{dir}{stem}.{hash}.{ext}"),
+        None => format!("{dir}{file_name}.{hash}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hashed_paths() {
+        assert_eq!(hashed_path("style.css", "ab12"), "style.ab12.css");
+        assert_eq!(
+            hashed_path("htmx-1.9.2/htmx.min.js", "ab12"),
+            "htmx-1.9.2/htmx.ab12.min.js"
+        );
+        assert_eq!(hashed_path("LICENSE", "ab12"), "LICENSE.ab12");
     }
 }
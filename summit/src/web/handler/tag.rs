@@ -0,0 +1,52 @@
+use crate::{
+    web::{
+        error::{WebError, WebPath},
+        extension::viewer::Viewer,
+        handler::{community::CommunityPost, feed::FeedLink},
+        template::{meta::PageMeta, Template},
+    },
+    Summit,
+};
+use axum::{extract::State, Extension};
+use sailfish::TemplateOnce;
+use std::sync::Arc;
+
+#[derive(Debug, TemplateOnce)]
+#[template(path = "page/tag.stpl")]
+pub struct TagPage<P: Iterator<Item = CommunityPost>> {
+    pub title: String,
+    pub viewer: Viewer,
+    pub meta: PageMeta,
+    pub tag: String,
+    pub posts: P,
+}
+
+/// List posts tagged with the given tag, across all communities.
+pub async fn handler(
+    State(summit): State<Arc<Summit>>,
+    WebPath(tag): WebPath<String>,
+    Extension(viewer): Extension<Viewer>,
+) -> Result<Template<TagPage<impl Iterator<Item = CommunityPost>>>, WebError> {
+    // Tags are stored normalized, see `content::ContentRefs`.
+    let tag = tag.trim_start_matches('#').to_lowercase();
+    let date_prefs = viewer.prefs.date_time();
+    let posts = summit
+        .tag_posts(&tag, viewer.prefs.posts_per_page())
+        .await?;
+    let meta = PageMeta {
+        url: Some(summit.fedi_config().url(&format!("/t/{tag}"))),
+        description: Some(format!("Posts tagged #{tag}")),
+        feed: Some(FeedLink::tag(&tag)),
+        ..PageMeta::new(format!("#{tag}"))
+    }
+    .with_postings(summit.fedi_config(), &posts);
+    Ok(Template(TagPage {
+        title: format!("#{tag}"),
+        viewer,
+        meta,
+        tag,
+        posts: posts
+            .into_iter()
+            .map(move |post| CommunityPost::new(date_prefs, post)),
+    }))
+}
@@ -0,0 +1,156 @@
+use crate::{fedi::addr::FediAddr, Summit};
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{debug, error};
+
+/// The content type WebFinger responses are served with, per RFC 7033.
+pub const JRD_CONTENT_TYPE: &str = "application/jrd+json";
+pub const ACTIVITY_JSON_CONTENT_TYPE: &str = "application/activity+json";
+
+#[derive(Debug, Deserialize)]
+pub struct WebfingerQuery {
+    pub resource: String,
+}
+/// A JSON Resource Descriptor, the WebFinger response document.
+#[derive(Debug, Serialize)]
+pub struct Jrd {
+    pub subject: String,
+    pub aliases: Vec<String>,
+    pub links: Vec<JrdLink>,
+}
+#[derive(Debug, Serialize)]
+pub struct JrdLink {
+    pub rel: &'static str,
+    #[serde(rename = "type")]
+    pub type_: &'static str,
+    pub href: String,
+}
+
+pub async fn handler(
+    State(summit): State<Arc<Summit>>,
+    Query(WebfingerQuery { resource }): Query<WebfingerQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    debug!(resource, "webfinger lookup");
+    // NIT: Only `acct:` resources are supported, actor urls would be nice too.
+    let addr = FediAddr::parse(&resource).map_err(|err| {
+        debug!(%err, "invalid webfinger resource");
+        StatusCode::BAD_REQUEST
+    })?;
+    let fedi_config = summit.fedi_config();
+    if !addr.host.eq_ignore_ascii_case(fedi_config.host()) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let actor = summit
+        .local_actor(&addr.user)
+        .await
+        .map_err(|err| {
+            error!(?err, "failed to lookup webfinger actor");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let href = fedi_config.actor_url(&actor);
+    let jrd = Jrd {
+        subject: FediAddr {
+            user: actor.name,
+            host: fedi_config.host().into(),
+        }
+        .acct()
+        .into(),
+        aliases: vec![href.clone()],
+        links: vec![
+            JrdLink {
+                rel: "self",
+                type_: ACTIVITY_JSON_CONTENT_TYPE,
+                href: href.clone(),
+            },
+            JrdLink {
+                rel: "http://webfinger.net/rel/profile-page",
+                type_: "text/html",
+                href,
+            },
+        ],
+    };
+    Ok(([(header::CONTENT_TYPE, JRD_CONTENT_TYPE)], Json(jrd)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        db::{Author, CreatePost},
+        dev::db::{DevDb, DEV_COMMUNITY},
+        SummitConfig,
+    };
+
+    async fn lookup(summit: &Arc<Summit>, resource: &str) -> Result<serde_json::Value, StatusCode> {
+        let query = WebfingerQuery {
+            resource: resource.to_owned(),
+        };
+        let res = handler(State(Arc::clone(summit)), Query(query))
+            .await?
+            .into_response();
+        assert_eq!(res.headers()[header::CONTENT_TYPE], JRD_CONTENT_TYPE);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        Ok(serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn resolves_local_actors() {
+        let summit = Arc::new(Summit::new(
+            SummitConfig::default(),
+            Box::<DevDb>::default(),
+        ));
+        let author = Author {
+            fedi_addr: FediAddr::parse("alice@localhost:3000").unwrap(),
+        };
+        summit
+            .create_post(CreatePost {
+                author,
+                title: "Title".into(),
+                body: "hi".into(),
+            })
+            .await
+            .unwrap();
+        let jrd = lookup(&summit, "acct:alice@localhost:3000").await.unwrap();
+        assert_eq!(jrd["subject"], "acct:alice@localhost:3000");
+        assert_eq!(jrd["links"][0]["rel"], "self");
+        assert_eq!(jrd["links"][0]["type"], ACTIVITY_JSON_CONTENT_TYPE);
+        assert_eq!(jrd["links"][0]["href"], "http://localhost:3000/u/alice");
+        let jrd = lookup(&summit, &format!("@{DEV_COMMUNITY}@LOCALHOST:3000"))
+            .await
+            .unwrap();
+        assert_eq!(
+            jrd["subject"],
+            format!("acct:{DEV_COMMUNITY}@localhost:3000")
+        );
+        assert_eq!(
+            jrd["aliases"][0],
+            format!("http://localhost:3000/c/{DEV_COMMUNITY}")
+        );
+    }
+    #[tokio::test]
+    async fn rejects_unknown_and_malformed_resources() {
+        let summit = Arc::new(Summit::new(
+            SummitConfig::default(),
+            Box::<DevDb>::default(),
+        ));
+        for (resource, status) in [
+            ("acct:summit@remote.example", StatusCode::NOT_FOUND),
+            ("acct:nobody@localhost:3000", StatusCode::NOT_FOUND),
+            ("acct:summit", StatusCode::BAD_REQUEST),
+            ("https://localhost:3000/c/summit", StatusCode::BAD_REQUEST),
+        ] {
+            assert_eq!(
+                lookup(&summit, resource).await.unwrap_err(),
+                status,
+                "{resource}"
+            );
+        }
+    }
+}
@@ -1,22 +1,43 @@
-use futures::{Future, FutureExt};
-use kanal::AsyncReceiver;
-use tokio::signal;
-use tracing::{error, info};
+use futures::Future;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{signal, sync::watch};
+use tracing::info;
 
 /// A shutdown signal indicating that the server itself is being shutdown, so connections should be
 /// closed, state should be persisted (if needed), etc. Of course, do not rely on this signal to
 /// ensure validity of state, as it is only an indicator to a request. The server may still die at
 /// any moment.
+///
+/// Before the signal is sent, the server drains for a configured delay. It keeps serving, but fails
+/// readiness checks, so that load balancers stop routing to it before connections are closed.
+///
+/// Every clone observes the signal, including those which start waiting after it was sent.
 #[derive(Clone)]
-pub struct ShutdownSignal(AsyncReceiver<()>);
+pub struct ShutdownSignal {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+    draining: Arc<AtomicBool>,
+}
 impl ShutdownSignal {
     /// Construct a new [`ShutdownSignal`] and **start a background task** which listens for
-    /// standard OS signals.
+    /// standard OS signals, draining for `drain_delay` before propagating them.
     ///
     /// # Panics
     /// If [`tokio::signal`] is not able to construct various terminator signal watchers.
-    pub async fn new() -> Self {
-        let (sender, receiver) = kanal::bounded_async::<()>(2);
+    pub async fn new(drain_delay: Duration) -> Self {
+        let (sender, receiver) = watch::channel(false);
+        let this = Self {
+            sender: Arc::new(sender),
+            receiver,
+            draining: Arc::new(AtomicBool::new(false)),
+        };
+        let signal = this.clone();
         #[allow(clippy::let_underscore_future)]
         let _ = tokio::spawn(async move {
             let ctrl_c = async {
@@ -37,20 +58,67 @@ impl ShutdownSignal {
                 _ = ctrl_c => {},
                 _ = terminate => {},
             }
-            info!("shutdown signal received, propagating..");
-            if let Err(err) = sender.send(()).await {
-                error!(?err, "failed to propagate shutdown signal");
+            signal.drain();
+            if !drain_delay.is_zero() {
+                info!(?drain_delay, "shutdown signal received, draining..");
+                tokio::time::sleep(drain_delay).await;
             }
+            info!("shutdown signal received, propagating..");
+            signal.shutdown();
         });
-        Self(receiver)
+        this
+    }
+    /// Whether the server is draining, ahead of shutting down or as requested by [`Self::drain`].
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+    /// Start draining without shutting down, such as ahead of a planned restart.
+    pub fn drain(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+    /// Shut down now, without draining, as if a signal had been received and propagated.
+    pub fn shutdown(&self) {
+        self.drain();
+        self.sender.send_replace(true);
+    }
+    /// Whether the shutdown signal was sent.
+    pub fn is_shutdown(&self) -> bool {
+        *self.receiver.borrow()
     }
     /// Return a future that can be `await`ed, where the output indicates a shutdown signal was
-    /// sent.
+    /// sent. It resolves immediately if it already was.
     pub fn recv(&self) -> impl Future<Output = ()> + '_ {
-        // NIT: This feels really bad, but many consumers don't have a way to deal with a failed
-        // receiving channel. It's possible that we may want to simply suppress errors here,
-        // but not actually signal shutdown. Maybe return a never resolving future? Not
-        // sure.
-        self.0.recv().map(|res| res.unwrap_or(()))
+        let mut receiver = self.receiver.clone();
+        async move {
+            // The sender lives as long as `self`, so this can't fail.
+            let _ = receiver.wait_for(|shutdown| *shutdown).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn wakes_every_receiver() {
+        let shutdown_signal = ShutdownSignal::new(Duration::ZERO).await;
+        assert!(!shutdown_signal.is_shutdown());
+        let waiting = (0..3)
+            .map(|_| {
+                let shutdown_signal = shutdown_signal.clone();
+                tokio::spawn(async move { shutdown_signal.recv().await })
+            })
+            .collect::<Vec<_>>();
+        shutdown_signal.shutdown();
+        for handle in waiting {
+            tokio::time::timeout(Duration::from_secs(1), handle)
+                .await
+                .expect("receiver wasn't woken")
+                .unwrap();
+        }
+        assert!(shutdown_signal.is_draining());
+        // Late receivers see it too.
+        shutdown_signal.clone().recv().await;
     }
 }
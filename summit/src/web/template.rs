@@ -1,3 +1,4 @@
+use crate::content;
 use axum::response::{Html, IntoResponse, Response};
 use hyper::StatusCode;
 use pulldown_cmark::Parser;
@@ -7,6 +8,8 @@ use sailfish::{
 };
 use tracing::error;
 
+pub mod meta;
+
 /// A template response type, where `T` is a sailfish template.
 pub struct Template<T>(pub T);
 impl<T> IntoResponse for Template<T>
@@ -31,14 +34,20 @@ impl From<String> for MarkdownHtml {
         Self(s)
     }
 }
+impl MarkdownHtml {
+    /// Render the markdown to html, outside of a template. Such as for feeds.
+    pub fn to_html(&self) -> String {
+        let parser = content::linkify(Parser::new(&self.0));
+        let mut str_buf = String::with_capacity(self.0.capacity());
+        pulldown_cmark::html::push_html(&mut str_buf, parser);
+        str_buf
+    }
+}
 impl Render for MarkdownHtml {
     fn render(&self, b: &mut Buffer) -> Result<(), RenderError> {
-        let parser = Parser::new(&self.0);
         // TODO: Reduce allocation here. As far as i can tell this is blocked due to
         // cmark requiring a &mut String, and `Buffer` being only fmt::Write. Cmark has a pull
         // request which may help this?
-        let mut str_buf = String::with_capacity(self.0.capacity());
-        pulldown_cmark::html::push_html(&mut str_buf, parser);
-        str_buf.render(b)
+        self.to_html().render(b)
     }
 }
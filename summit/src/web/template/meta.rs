@@ -0,0 +1,183 @@
+//! Page metadata for link previews, rendered into the page head by `layout/head.stpl`.
+use crate::{
+    content,
+    date_time::DateTime,
+    db::{Post, Profile},
+    fedi::FediConfig,
+    web::handler::feed::FeedLink,
+};
+use sailfish::TemplateOnce;
+use serde::Serialize;
+
+/// The maximum length of descriptions, in characters. Most previews show less.
+const DESCRIPTION_LEN: usize = 200;
+
+/// The OpenGraph and Twitter card metadata of a page, its feeds, and any structured data.
+#[derive(Debug, Default, TemplateOnce)]
+#[template(path = "component/page_meta.stpl")]
+pub struct PageMeta {
+    pub title: String,
+    pub kind: OgKind,
+    /// The absolute url of the page.
+    pub url: Option<String>,
+    pub description: Option<String>,
+    /// An absolute url of an image representing the page, such as an avatar.
+    pub image: Option<String>,
+    pub author: Option<String>,
+    pub published: Option<DateTime>,
+    pub feed: Option<FeedLink>,
+    pub postings: Vec<DiscussionForumPosting>,
+}
+impl PageMeta {
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            ..Default::default()
+        }
+    }
+    /// The metadata of a page dedicated to a single post.
+    pub fn post(config: &FediConfig, post: &Post) -> Self {
+        let posting = DiscussionForumPosting::new(config, post, true);
+        Self {
+            title: posting.headline.clone(),
+            kind: OgKind::Article,
+            url: Some(posting.url.clone()),
+            description: Some(content::snippet(&post.body, DESCRIPTION_LEN)),
+            image: None,
+            author: Some(posting.author.name.clone()),
+            published: Some(post.created_on),
+            feed: None,
+            postings: vec![posting],
+        }
+    }
+    /// The metadata of a profile page.
+    pub fn profile(config: &FediConfig, profile: &Profile, display_name: &str) -> Self {
+        Self {
+            title: display_name.to_owned(),
+            kind: OgKind::Profile,
+            url: Some(profile_url(config, profile)),
+            description: profile
+                .bio
+                .as_deref()
+                .map(|bio| content::snippet(bio, DESCRIPTION_LEN)),
+            image: profile.avatar_url.clone(),
+            ..Default::default()
+        }
+    }
+    /// Include the given posts as structured data, without their text.
+    pub fn with_postings<'a>(
+        mut self,
+        config: &FediConfig,
+        posts: impl IntoIterator<Item = &'a Post>,
+    ) -> Self {
+        self.postings = posts
+            .into_iter()
+            .map(|post| DiscussionForumPosting::new(config, post, false))
+            .collect();
+        self
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OgKind {
+    #[default]
+    Website,
+    Article,
+    Profile,
+}
+impl OgKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Website => "website",
+            Self::Article => "article",
+            Self::Profile => "profile",
+        }
+    }
+}
+
+/// A schema.org `DiscussionForumPosting`, embedded as JSON-LD.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscussionForumPosting {
+    #[serde(rename = "@context")]
+    pub context: &'static str,
+    #[serde(rename = "@type")]
+    pub type_: &'static str,
+    pub identifier: String,
+    pub url: String,
+    pub headline: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub author: Person,
+    pub date_published: DateTime,
+}
+impl DiscussionForumPosting {
+    pub fn new(config: &FediConfig, post: &Post, with_text: bool) -> Self {
+        let addr = &post.author.fedi_addr;
+        Self {
+            context: "https://schema.org",
+            type_: "DiscussionForumPosting",
+            identifier: post.id.to_string(),
+            url: config.url(&format!("/p/{}", post.id)),
+            headline: content::snippet(&post.title, usize::MAX),
+            text: with_text.then(|| content::snippet(&post.body, usize::MAX)),
+            author: Person {
+                type_: "Person",
+                name: addr.to_string(),
+                url: config.url(&format!("/u/{}@{}", addr.user, addr.host)),
+            },
+            date_published: post.created_on,
+        }
+    }
+    /// Serialize for a `<script type="application/ld+json">` element, escaping anything that
+    /// could close the element early.
+    pub fn to_script_json(&self) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        json.replace('<', "\\u003c")
+            .replace('>', "\\u003e")
+            .replace('&', "\\u0026")
+    }
+}
+#[derive(Debug, Serialize)]
+pub struct Person {
+    #[serde(rename = "@type")]
+    pub type_: &'static str,
+    pub name: String,
+    pub url: String,
+}
+
+fn profile_url(config: &FediConfig, profile: &Profile) -> String {
+    let addr = &profile.fedi_addr;
+    config.url(&format!("/u/{}@{}", addr.user, addr.host))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{content::ContentRefs, db::Author, fedi::addr::FediAddr, uuid::PostId};
+
+    #[test]
+    fn script_json_cannot_close_its_element() {
+        // As code, so that the tags are kept in the plain text.
+        let post = Post {
+            id: PostId::new(),
+            author: Author {
+                fedi_addr: FediAddr::parse("alice@summit.example").unwrap(),
+            },
+            created_on: DateTime::now(),
+            title: "`</script><script>alert('title')</script>`".into(),
+            body: "a & b `<!-- </SCRIPT>`".into(),
+            refs: ContentRefs::default(),
+        };
+        let json =
+            DiscussionForumPosting::new(&FediConfig::default(), &post, true).to_script_json();
+        assert!(!json.contains(['<', '>', '&']), "{json}");
+        // Escaped as JSON, the text is unchanged.
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value["headline"],
+            "</script><script>alert('title')</script>"
+        );
+        assert_eq!(value["text"], "a & b <!-- </SCRIPT>");
+    }
+}
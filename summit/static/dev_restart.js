@@ -16,6 +16,10 @@ function setupEventSource() {
     }
     connectedOnce = true;
   };
+  // Sent as static assets change on disk, without a restart.
+  eventSource.addEventListener("reload", function() {
+    location.reload();
+  });
   eventSource.onerror = function(e) {
     eventSource.close();
     let timeout = timeoutState;